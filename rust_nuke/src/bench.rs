@@ -1,6 +1,17 @@
 use {
     crate::bench_tps_client::*,
     crate::blockhash::*,
+    crate::cli::Config,
+    crate::keypair_store::{load_or_generate_keypairs, record_funding},
+    crate::latency::{create_confirmation_thread, LatencyStats, SignatureTracker},
+    crate::nonce::{
+        create_nonce_accounts, get_durable_nonces, get_nonce_accounts, nonce_accounts,
+        nonce_keypair, nonce_rent, nonced_message, wait_for_nonce_hashes, DurableNonces,
+    },
+    crate::perf_utils::{compute_and_report_stats, sample_txs, SampleStats},
+    crate::priority_fee::{compute_unit_price_of, ComputeBudget},
+    crate::rate_limit::RateLimiter,
+    crate::workload::Workload,
     log::{debug, error, info, trace, warn},
     rayon::prelude::*,
     solana_client::{
@@ -13,21 +24,21 @@ use {
     solana_sdk::{
         clock::{DEFAULT_MS_PER_SLOT, DEFAULT_S_PER_SLOT, MAX_PROCESSING_AGE},
         commitment_config::CommitmentConfig,
-        hash::Hash,
+        hash::{hashv, Hash},
         instruction::{AccountMeta, Instruction},
         message::Message,
         native_token::Sol,
         pubkey::Pubkey,
         signature::{read_keypair_file, Keypair, Signer},
-        system_instruction,
-        system_transaction,
-        // timing::{duration_as_ms, duration_as_s, duration_as_us, timestamp},
+        system_instruction, system_transaction,
+        timing::{duration_as_ms, duration_as_s, timestamp},
         transaction::Transaction,
     },
     std::{
-        collections::{HashSet, VecDeque},
+        collections::{HashMap, HashSet, VecDeque},
         fs::File,
         io::Read,
+        path::Path,
         process::exit,
         // process::exit,
         sync::{
@@ -46,8 +57,554 @@ const MAX_TX_QUEUE_AGE: u64 = (MAX_PROCESSING_AGE as f64 * DEFAULT_S_PER_SLOT) a
 
 pub const MAX_SPENDS_PER_TX: u64 = 4;
 
+// Rounds of sending and verifying a chunk of funding transfers before giving up on it
+const MAX_FUNDING_TRIES: usize = 10;
+
+// Rate limited senders split each chunk into batches this size, so a chunk goes out at the
+// target rate instead of in one burst
+const RATE_LIMITED_BATCH_SIZE: usize = 16;
+
 pub type SharedTransactions = Arc<RwLock<VecDeque<Vec<(Transaction, u64)>>>>;
 
+/// What a spam run did, as seen from the client
+#[derive(Debug, Clone, Default)]
+pub struct BenchTpsStats {
+    /// Transactions handed to `send_batch`
+    pub tx_sent: usize,
+    /// Transactions dropped from the queue for being older than `MAX_TX_QUEUE_AGE`
+    pub tx_expired: usize,
+    /// Time spent generating and sending
+    pub elapsed: Duration,
+    /// What the sampler observed on the cluster
+    pub sample_stats: SampleStats,
+    /// How long the sent transactions took to land
+    pub latency: LatencyStats,
+}
+
+fn get_transaction_count<T: BenchTpsClient>(client: &Arc<T>) -> u64 {
+    loop {
+        match client.get_transaction_count() {
+            Ok(count) => break count,
+            Err(err) => {
+                info!("Couldn't get transaction count: {:?}", err);
+                sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+fn create_sampler_thread<T>(
+    client: &Arc<T>,
+    exit_signal: &Arc<AtomicBool>,
+    sample_period: u64,
+    maxes: &Arc<RwLock<Vec<(String, SampleStats)>>>,
+) -> JoinHandle<()>
+where
+    T: 'static + BenchTpsClient + Send + Sync,
+{
+    info!("Sampling TPS every {} second...", sample_period);
+    let exit_signal = exit_signal.clone();
+    let maxes = maxes.clone();
+    let client = client.clone();
+    Builder::new()
+        .name("solana-client-sample".to_string())
+        .spawn(move || {
+            sample_txs(&exit_signal, &maxes, sample_period, &client);
+        })
+        .unwrap()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_sender_threads<T>(
+    client: &Arc<T>,
+    shared_txs: &SharedTransactions,
+    thread_batch_sleep_ms: usize,
+    total_tx_sent_count: &Arc<AtomicUsize>,
+    total_tx_expired_count: &Arc<AtomicUsize>,
+    signature_tracker: &Arc<SignatureTracker>,
+    rate_limiter: &Option<Arc<RateLimiter>>,
+    threads: usize,
+    exit_signal: &Arc<AtomicBool>,
+    shared_tx_active_thread_count: &Arc<AtomicIsize>,
+) -> Vec<JoinHandle<()>>
+where
+    T: 'static + BenchTpsClient + Send + Sync,
+{
+    (0..threads)
+        .map(|_| {
+            let exit_signal = exit_signal.clone();
+            let shared_txs = shared_txs.clone();
+            let shared_tx_active_thread_count = shared_tx_active_thread_count.clone();
+            let total_tx_sent_count = total_tx_sent_count.clone();
+            let total_tx_expired_count = total_tx_expired_count.clone();
+            let signature_tracker = signature_tracker.clone();
+            let rate_limiter = rate_limiter.clone();
+            let client = client.clone();
+            Builder::new()
+                .name("solana-client-sender".to_string())
+                .spawn(move || {
+                    do_tx_transfers(
+                        &exit_signal,
+                        &shared_txs,
+                        &shared_tx_active_thread_count,
+                        &total_tx_sent_count,
+                        &total_tx_expired_count,
+                        &signature_tracker,
+                        rate_limiter.as_deref(),
+                        thread_batch_sleep_ms,
+                        &client,
+                    );
+                })
+                .unwrap()
+        })
+        .collect()
+}
+
+/// Spam the cluster with transfers between the halves of `gen_keypairs` until the configured
+/// duration or transaction limit is reached, or until `interrupted` is set.
+pub fn do_bench_tps<T>(
+    client: Arc<T>,
+    config: &Config,
+    gen_keypairs: &[Keypair],
+    interrupted: &AtomicBool,
+) -> BenchTpsStats
+where
+    T: 'static + BenchTpsClient + Send + Sync,
+{
+    let Config {
+        threads,
+        thread_batch_sleep_ms,
+        sample_period,
+        rate_profile,
+        ..
+    } = *config;
+
+    // Each round transfers from the first half of the keypairs into the second half
+    let tx_count = gen_keypairs.len() / 2;
+    assert!(
+        tx_count > 0,
+        "need at least two keypairs to generate transfers"
+    );
+    let source_keypairs: Vec<&Keypair> = gen_keypairs[..tx_count].iter().collect();
+    let mut dest_keypairs: VecDeque<&Keypair> =
+        gen_keypairs[tx_count..2 * tx_count].iter().collect();
+
+    let first_tx_count = get_transaction_count(&client);
+    info!("Initial transaction count {}", first_tx_count);
+
+    let exit_signal = Arc::new(AtomicBool::new(false));
+
+    // Sample the cluster transaction count every period and
+    // collect the max transaction rate and total tx count seen
+    let maxes = Arc::new(RwLock::new(Vec::new()));
+    let sample_thread = create_sampler_thread(&client, &exit_signal, sample_period, &maxes);
+
+    let shared_txs: SharedTransactions = Arc::new(RwLock::new(VecDeque::new()));
+
+    let blockhash_service = if config.blockhash_subscription {
+        BlockhashService::subscribe(&client, &config.websocket_url).unwrap_or_else(|err| {
+            warn!("{}, polling for the blockhash instead", err);
+            BlockhashService::new(&client)
+        })
+    } else {
+        BlockhashService::new(&client)
+    };
+    let shared_tx_active_thread_count = Arc::new(AtomicIsize::new(0));
+    let total_tx_sent_count = Arc::new(AtomicUsize::new(0));
+    let total_tx_expired_count = Arc::new(AtomicUsize::new(0));
+
+    // The tracker outlives the senders so it can wait for the last transactions to land
+    let signature_tracker = Arc::new(SignatureTracker::default());
+    let tracker_exit_signal = Arc::new(AtomicBool::new(false));
+    let tracker_thread =
+        create_confirmation_thread(&client, &signature_tracker, &tracker_exit_signal);
+
+    // Shared by every sender thread so the target applies to the run as a whole
+    let rate_limiter = rate_profile.map(|profile| Arc::new(RateLimiter::new(profile)));
+
+    let s_threads = create_sender_threads(
+        &client,
+        &shared_txs,
+        thread_batch_sleep_ms,
+        &total_tx_sent_count,
+        &total_tx_expired_count,
+        &signature_tracker,
+        &rate_limiter,
+        threads,
+        &exit_signal,
+        &shared_tx_active_thread_count,
+    );
+
+    let start = Instant::now();
+
+    if let Err(err) = generate_chunked_transfers(
+        &client,
+        &blockhash_service,
+        &shared_txs,
+        shared_tx_active_thread_count,
+        &source_keypairs,
+        &mut dest_keypairs,
+        config,
+        interrupted,
+    ) {
+        error!("Run stopped early: {}", err);
+    }
+
+    // Stop the sender and sampling threads so the sampler will collect the stats
+    exit_signal.store(true, Ordering::Relaxed);
+
+    info!("Waiting for sampler threads...");
+    if let Err(err) = sample_thread.join() {
+        info!("  join() failed with: {:?}", err);
+    }
+
+    info!("Waiting for transmit threads...");
+    for t in s_threads {
+        if let Err(err) = t.join() {
+            info!("  join() failed with: {:?}", err);
+        }
+    }
+
+    info!("Waiting for blockhash thread...");
+    if let Err(err) = blockhash_service.shutdown() {
+        error!("Run stopped early: {}", err);
+    }
+
+    let elapsed = start.elapsed();
+
+    info!("Waiting for sent transactions to finalize...");
+    tracker_exit_signal.store(true, Ordering::Relaxed);
+    if let Err(err) = tracker_thread.join() {
+        info!("  join() failed with: {:?}", err);
+    }
+
+    let tx_sent = total_tx_sent_count.load(Ordering::Relaxed);
+    compute_and_report_stats(&maxes, sample_period, &elapsed, tx_sent);
+    let latency = signature_tracker.stats();
+    latency.print();
+
+    let sample_stats = maxes
+        .read()
+        .unwrap()
+        .first()
+        .map(|(_addr, stats)| stats.clone())
+        .unwrap_or_default();
+    BenchTpsStats {
+        tx_sent,
+        tx_expired: total_tx_expired_count.load(Ordering::Relaxed),
+        elapsed,
+        sample_stats,
+        latency,
+    }
+}
+
+/// How far through the run we are, from 0.0 to 1.0, by whichever of the duration and the
+/// transaction limit is closer to being reached
+fn run_progress(
+    elapsed: Duration,
+    duration: Duration,
+    total_generated: usize,
+    tx_limit: Option<usize>,
+) -> f64 {
+    let by_time = duration_as_s(&elapsed) as f64 / duration.as_secs_f64();
+    let by_count = tx_limit.map_or(0.0, |limit| total_generated as f64 / limit as f64);
+    by_time.max(by_count)
+}
+
+fn generate_chunked_transfers<T: BenchTpsClient>(
+    client: &Arc<T>,
+    blockhash: &BlockhashService,
+    shared_txs: &SharedTransactions,
+    shared_tx_active_thread_count: Arc<AtomicIsize>,
+    source_keypairs: &[&Keypair],
+    dest_keypairs: &mut VecDeque<&Keypair>,
+    config: &Config,
+    interrupted: &AtomicBool,
+) -> Result<()> {
+    let Config {
+        threads,
+        duration,
+        tx_limit,
+        sustained,
+        ..
+    } = *config;
+
+    let nonce_accounts = if config.use_durable_nonce {
+        let authorities: Vec<&Keypair> = source_keypairs
+            .iter()
+            .chain(dest_keypairs.iter())
+            .copied()
+            .collect();
+        nonce_accounts(&authorities)
+    } else {
+        HashMap::new()
+    };
+
+    // generate and send transactions for the specified duration
+    let start = Instant::now();
+    let mut reclaim_lamports_back_to_source_account = false;
+    let mut total_generated = 0;
+    // Stop when interrupted, or when the blockhash poller gives up and nothing can be signed
+    let exited = || interrupted.load(Ordering::Relaxed) || blockhash.is_stopped();
+    while start.elapsed() < duration
+        && tx_limit.map_or(true, |limit| total_generated < limit)
+        && !exited()
+    {
+        let progress = run_progress(start.elapsed(), duration, total_generated, tx_limit);
+        // A nonce only advances once the transaction using it lands, so a sender whose last
+        // transaction is still in flight signs with the same nonce again and only one of the
+        // two can land
+        let durable_nonces = config.use_durable_nonce.then(|| {
+            let senders: Vec<&Keypair> = if reclaim_lamports_back_to_source_account {
+                dest_keypairs.iter().copied().collect()
+            } else {
+                source_keypairs.to_vec()
+            };
+            get_durable_nonces(client.as_ref(), &senders, &nonce_accounts)
+        });
+        let durable_nonces = durable_nonces.transpose()?;
+        total_generated += generate_txs(
+            shared_txs,
+            &blockhash.blockhash(),
+            source_keypairs,
+            dest_keypairs,
+            threads,
+            reclaim_lamports_back_to_source_account,
+            &config.workload,
+            &config
+                .compute_budget
+                .instructions(config.compute_budget.price_at(progress)),
+            durable_nonces.as_ref(),
+        );
+
+        // In sustained mode, overlap the transfers with generation. This has higher average
+        // performance but lower peak performance in tested environments.
+        if sustained {
+            // Ensure that we don't generate more transactions than we can handle.
+            while shared_txs.read().unwrap().len() > 2 * threads && !exited() {
+                sleep(Duration::from_millis(1));
+            }
+        } else {
+            while (!shared_txs.read().unwrap().is_empty()
+                || shared_tx_active_thread_count.load(Ordering::Relaxed) > 0)
+                && !exited()
+            {
+                sleep(Duration::from_millis(1));
+            }
+        }
+
+        // Rotate destination keypairs so that the next round of transactions will have different
+        // transaction signatures even when blockhash is reused.
+        dest_keypairs.rotate_left(1);
+
+        // Switch directions after every round so the lamports flow back and forth
+        reclaim_lamports_back_to_source_account = !reclaim_lamports_back_to_source_account;
+    }
+
+    // Let the sender threads drain whatever is still queued
+    while (!shared_txs.read().unwrap().is_empty()
+        || shared_tx_active_thread_count.load(Ordering::Relaxed) > 0)
+        && !exited()
+    {
+        sleep(Duration::from_millis(1));
+    }
+    Ok(())
+}
+
+pub(crate) fn generate_workload_txs(
+    source: &[&Keypair],
+    dest: &VecDeque<&Keypair>,
+    reclaim: bool,
+    blockhash: &Hash,
+    workload: &Workload,
+    compute_budget: &[Instruction],
+    durable_nonces: Option<&DurableNonces>,
+) -> Vec<(Transaction, u64)> {
+    let pairs: Vec<_> = if !reclaim {
+        source.iter().zip(dest.iter()).collect()
+    } else {
+        dest.iter().zip(source.iter()).collect()
+    };
+
+    pairs
+        .par_iter()
+        .map(|(from, to)| {
+            let (blockhash, nonce_account) = match durable_nonces {
+                Some(durable_nonces) => {
+                    let (nonce_account, nonce) = &durable_nonces[&from.pubkey()];
+                    (nonce, Some(nonce_account))
+                }
+                None => (blockhash, None),
+            };
+            (
+                workload.transaction(
+                    from,
+                    &to.pubkey(),
+                    blockhash,
+                    nonce_account,
+                    compute_budget,
+                    &mut rand::thread_rng(),
+                ),
+                timestamp(),
+            )
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate_txs(
+    shared_txs: &SharedTransactions,
+    blockhash: &Hash,
+    source: &[&Keypair],
+    dest: &VecDeque<&Keypair>,
+    threads: usize,
+    reclaim: bool,
+    workload: &Workload,
+    compute_budget: &[Instruction],
+    durable_nonces: Option<&DurableNonces>,
+) -> usize {
+    let tx_count = source.len();
+    info!(
+        "Signing transactions... {} (reclaim={}, blockhash={})",
+        tx_count, reclaim, blockhash
+    );
+    let signing_start = Instant::now();
+
+    let transactions = generate_workload_txs(
+        source,
+        dest,
+        reclaim,
+        blockhash,
+        workload,
+        compute_budget,
+        durable_nonces,
+    );
+
+    let duration = signing_start.elapsed();
+    let ns = duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos());
+    let bsps = (tx_count) as f64 / ns as f64;
+    let nsps = ns as f64 / (tx_count) as f64;
+    info!(
+        "Done. {:.2} thousand signatures per second, {:.2} us per signature, {} ms total time, {}",
+        bsps * 1_000_000_f64,
+        nsps / 1_000_f64,
+        duration_as_ms(&duration),
+        blockhash,
+    );
+
+    let generated = transactions.len();
+    let sz = std::cmp::max(transactions.len() / threads, 1);
+    let chunks: Vec<_> = transactions.chunks(sz).collect();
+    {
+        let mut shared_txs_wl = shared_txs.write().unwrap();
+        for chunk in chunks {
+            shared_txs_wl.push_back(chunk.to_vec());
+        }
+    }
+    generated
+}
+
+#[allow(clippy::too_many_arguments)]
+fn do_tx_transfers<T: BenchTpsClient>(
+    exit_signal: &Arc<AtomicBool>,
+    shared_txs: &SharedTransactions,
+    shared_tx_thread_count: &Arc<AtomicIsize>,
+    total_tx_sent_count: &Arc<AtomicUsize>,
+    total_tx_expired_count: &Arc<AtomicUsize>,
+    signature_tracker: &Arc<SignatureTracker>,
+    rate_limiter: Option<&RateLimiter>,
+    thread_batch_sleep_ms: usize,
+    client: &Arc<T>,
+) {
+    loop {
+        // The rate limiter does the pacing when there is one
+        if thread_batch_sleep_ms > 0 && rate_limiter.is_none() {
+            sleep(Duration::from_millis(thread_batch_sleep_ms as u64));
+        }
+        let txs = {
+            let mut shared_txs_wl = shared_txs.write().expect("write lock in do_tx_transfers");
+            let txs = shared_txs_wl.pop_front();
+            if txs.is_some() {
+                // Mark the batch as in flight before releasing the lock, so the generator
+                // never sees an empty queue with nothing in flight while we still hold it
+                shared_tx_thread_count.fetch_add(1, Ordering::Relaxed);
+            }
+            txs
+        };
+        if let Some(txs0) = txs {
+            info!("Transferring 1 unit {} times...", txs0.len());
+            let transfer_start = Instant::now();
+            let batch_size = match rate_limiter {
+                Some(_) => RATE_LIMITED_BATCH_SIZE,
+                None => txs0.len().max(1),
+            };
+            let mut tx_len = 0;
+            let mut old_transactions = 0;
+            let mut txs0 = txs0.into_iter().peekable();
+            while txs0.peek().is_some() {
+                let now = timestamp();
+                let mut transactions = Vec::<_>::with_capacity(batch_size);
+                for tx in txs0.by_ref().take(batch_size) {
+                    // Transactions that are too old will be rejected by the cluster. Don't
+                    // bother sending them.
+                    if now > tx.1 && now - tx.1 > 1000 * MAX_TX_QUEUE_AGE {
+                        old_transactions += 1;
+                        continue;
+                    }
+                    transactions.push(tx.0);
+                }
+                if transactions.is_empty() {
+                    continue;
+                }
+                let target_tps = match rate_limiter
+                    .map(|limiter| limiter.acquire(transactions.len(), exit_signal))
+                {
+                    // Stopping, so the rest of the batch is never sent
+                    Some(None) => break,
+                    target_tps => target_tps.flatten(),
+                };
+                tx_len += transactions.len();
+                let signatures: Vec<_> = transactions
+                    .iter()
+                    .map(|tx| (tx.signatures[0], compute_unit_price_of(tx)))
+                    .collect();
+
+                let send_time = Instant::now();
+                if let Err(error) = client.send_batch(transactions) {
+                    warn!("send_batch in do_tx_transfers failed: {}", error);
+                }
+                signature_tracker.track(signatures, send_time, target_tps);
+            }
+
+            if old_transactions > 0 {
+                // Everything queued behind an expired batch is at least as old, drop it all
+                let mut shared_txs_wl = shared_txs.write().expect("write lock in do_tx_transfers");
+                old_transactions += shared_txs_wl.iter().map(Vec::len).sum::<usize>();
+                shared_txs_wl.clear();
+                total_tx_expired_count.fetch_add(old_transactions, Ordering::Relaxed);
+            }
+            shared_tx_thread_count.fetch_add(-1, Ordering::Relaxed);
+            total_tx_sent_count.fetch_add(tx_len, Ordering::Relaxed);
+            info!(
+                "Tx send done. {} ms {} tps",
+                duration_as_ms(&transfer_start.elapsed()),
+                tx_len as f32 / duration_as_s(&transfer_start.elapsed()),
+            );
+        }
+        if exit_signal.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+}
+
+/// Sum of the balances of `keypairs`, counting accounts that can't be read as empty
+pub fn total_balance<T: BenchTpsClient>(client: &Arc<T>, keypairs: &[Keypair]) -> u64 {
+    keypairs
+        .iter()
+        .map(|keypair| client.get_balance(&keypair.pubkey()).unwrap_or(0))
+        .sum()
+}
+
 fn verify_funding_transfer<T: BenchTpsClient>(
     client: &Arc<T>,
     tx: &Transaction,
@@ -68,10 +625,18 @@ trait FundingTransactions<'a> {
         client: &Arc<T>,
         to_fund: &[(&'a Keypair, Vec<(Pubkey, u64)>)],
         to_lamports: u64,
+        compute_budget: &ComputeBudget,
+        blockhash: Option<&BlockhashService>,
+    ) -> Result<()>;
+    fn make(
+        &mut self,
+        to_fund: &[(&'a Keypair, Vec<(Pubkey, u64)>)],
+        compute_budget: &ComputeBudget,
+        use_durable_nonce: bool,
     );
-    fn make(&mut self, to_fund: &[(&'a Keypair, Vec<(Pubkey, u64)>)]);
-    fn sign(&mut self, blockhash: &Arc<RwLock<Hash>>);
-    fn send<T: BenchTpsClient>(&self, client: &Arc<T>);
+    fn sign(&mut self, blockhash: &Hash);
+    fn sign_with_nonces<T: BenchTpsClient>(&mut self, client: &Arc<T>) -> Result<()>;
+    fn send<T: BenchTpsClient>(&self, client: &Arc<T>) -> Result<()>;
     fn verify<T: 'static + BenchTpsClient + Send + Sync>(
         &mut self,
         client: &Arc<T>,
@@ -85,28 +650,16 @@ impl<'a> FundingTransactions<'a> for Vec<(&'a Keypair, Transaction)> {
         client: &Arc<T>,
         to_fund: &[(&'a Keypair, Vec<(Pubkey, u64)>)],
         to_lamports: u64,
-    ) {
-        self.make(to_fund);
-
-        let blockhash = Arc::new(RwLock::new(get_latest_blockhash(client.as_ref())));
-
-        let exit_signal = Arc::new(AtomicBool::new(false));
-
-        let blockhash_thread = {
-            let exit_signal = exit_signal.clone();
-            let blockhash = blockhash.clone();
-            let client = client.clone();
-            // let id = id.pubkey();
-            Builder::new()
-                .name("solana-blockhash-poller".to_string())
-                .spawn(move || {
-                    poll_blockhash(&exit_signal, &blockhash, &client);
-                })
-                .unwrap()
-        };
+        compute_budget: &ComputeBudget,
+        blockhash: Option<&BlockhashService>,
+    ) -> Result<()> {
+        // Nonced transactions don't expire, so they're signed without a blockhash
+        let use_durable_nonce = blockhash.is_none();
+        self.make(to_fund, compute_budget, use_durable_nonce);
 
         let mut tries = 0;
-        while !self.is_empty() {
+        let stopped = || blockhash.map_or(false, BlockhashService::is_stopped);
+        while !self.is_empty() && tries < MAX_FUNDING_TRIES && !stopped() {
             println!(
                 "{} {} each to {} accounts in {} txs",
                 if tries == 0 {
@@ -119,9 +672,13 @@ impl<'a> FundingTransactions<'a> for Vec<(&'a Keypair, Transaction)> {
                 self.len(),
             );
 
-            // re-sign retained to_fund_txes with updated blockhash
-            self.sign(&blockhash);
-            self.send(client);
+            // re-sign retained to_fund_txes with updated blockhash. A nonced transaction that
+            // hasn't landed still has the same nonce, so it's simply sent again.
+            match blockhash {
+                Some(blockhash) => self.sign(&blockhash.blockhash()),
+                None => self.sign_with_nonces(client)?,
+            }
+            self.send(client)?;
 
             // Sleep a few slots to allow transactions to process
             sleep(Duration::from_secs(1));
@@ -133,16 +690,38 @@ impl<'a> FundingTransactions<'a> for Vec<(&'a Keypair, Transaction)> {
             //  retry
             tries += 1;
         }
+
+        // The poller is only stopped under us when it gives up on the blockhash
+        if stopped() {
+            return Err(BenchTpsError::BlockhashStuck);
+        }
+        if !self.is_empty() {
+            return Err(BenchTpsError::FundingIncomplete {
+                remaining: self.len() * MAX_SPENDS_PER_TX as usize,
+            });
+        }
         info!("transferred");
+        Ok(())
     }
 
-    fn make(&mut self, to_fund: &[(&'a Keypair, Vec<(Pubkey, u64)>)]) {
+    fn make(
+        &mut self,
+        to_fund: &[(&'a Keypair, Vec<(Pubkey, u64)>)],
+        compute_budget: &ComputeBudget,
+        use_durable_nonce: bool,
+    ) {
         let mut make_txs = Measure::start("make_txs");
+        let budget_instructions = compute_budget.initial_instructions();
         let to_fund_txs: Vec<(&Keypair, Transaction)> = to_fund
             .par_iter()
             .map(|(k, t)| {
-                let instructions = system_instruction::transfer_many(&k.pubkey(), t);
-                let message = Message::new(&instructions, Some(&k.pubkey()));
+                let mut instructions = budget_instructions.clone();
+                instructions.extend(system_instruction::transfer_many(&k.pubkey(), t));
+                let message = if use_durable_nonce {
+                    nonced_message(&instructions, &k.pubkey(), &nonce_keypair(k).pubkey())
+                } else {
+                    Message::new(&instructions, Some(&k.pubkey()))
+                };
                 (*k, Transaction::new_unsigned(message))
             })
             .collect();
@@ -155,28 +734,47 @@ impl<'a> FundingTransactions<'a> for Vec<(&'a Keypair, Transaction)> {
         self.extend(to_fund_txs);
     }
 
-    fn sign(&mut self, blockhash: &Arc<RwLock<Hash>>) {
+    fn sign(&mut self, blockhash: &Hash) {
         for i in 0..1 {
-            println!("blockhash in sign method {}", *blockhash.read().unwrap());
+            println!("blockhash in sign method {}", blockhash);
             sleep(Duration::from_millis(1000));
         }
 
         let mut sign_txs = Measure::start("sign_txs");
         self.par_iter_mut().for_each(|(k, tx)| {
-            tx.sign(&[*k], *blockhash.read().unwrap());
+            tx.sign(&[*k], *blockhash);
         });
         sign_txs.stop();
         debug!("sign {} txs: {}us", self.len(), sign_txs.as_us());
     }
 
-    fn send<T: BenchTpsClient>(&self, client: &Arc<T>) {
+    fn sign_with_nonces<T: BenchTpsClient>(&mut self, client: &Arc<T>) -> Result<()> {
+        let nonce_pubkeys: Vec<Pubkey> = self
+            .par_iter()
+            .map(|(k, _tx)| nonce_keypair(k).pubkey())
+            .collect();
+        let nonces = wait_for_nonce_hashes(client.as_ref(), &nonce_pubkeys)?;
+
+        let mut sign_txs = Measure::start("sign_txs");
+        self.par_iter_mut()
+            .zip(nonces)
+            .for_each(|((k, tx), nonce)| {
+                tx.sign(&[*k], nonce);
+            });
+        sign_txs.stop();
+        debug!("sign {} txs: {}us", self.len(), sign_txs.as_us());
+        Ok(())
+    }
+
+    fn send<T: BenchTpsClient>(&self, client: &Arc<T>) -> Result<()> {
         let mut send_txs = Measure::start("send_and_clone_txs");
         let batch: Vec<_> = self.iter().map(|(_keypair, tx)| tx.clone()).collect();
 
         println!("sending batch");
-        client.send_batch(batch).expect("transfer");
+        client.send_batch(batch)?;
         send_txs.stop();
         debug!("send {} {}", self.len(), send_txs);
+        Ok(())
     }
 
     fn verify<T: 'static + BenchTpsClient + Send + Sync>(
@@ -260,6 +858,11 @@ impl<'a> FundingTransactions<'a> for Vec<(&'a Keypair, Transaction)> {
 /// fund the dests keys by spending all of the source keys into MAX_SPENDS_PER_TX
 /// on every iteration.  This allows us to replay the transfers because the source is either empty,
 /// or full
+///
+/// With `use_durable_nonce`, every keypair creates its nonce account as soon as it's funded and
+/// signs the transfers it funds the next level with using that nonce. The funding keypair signs
+/// with a recent blockhash instead, so it isn't left with a nonce account nothing closes.
+#[allow(clippy::too_many_arguments)]
 pub fn fund_keys<T: 'static + BenchTpsClient + Send + Sync>(
     client: Arc<T>,
     source: &Keypair,
@@ -267,7 +870,13 @@ pub fn fund_keys<T: 'static + BenchTpsClient + Send + Sync>(
     total: u64,
     max_fee: u64,
     lamports_per_account: u64,
-) {
+    compute_budget: &ComputeBudget,
+    use_durable_nonce: bool,
+) -> Result<()> {
+    // One poller for every level and chunk of the tree. Nonced transfers don't need one, so
+    // with durable nonces it only lasts for the funding keypair's level.
+    let mut blockhash_service = Some(BlockhashService::new(&client));
+
     let mut funded: Vec<&Keypair> = vec![source];
     let mut funded_funds = total;
     let mut not_funded: Vec<&Keypair> = dests.iter().collect();
@@ -277,7 +886,16 @@ pub fn fund_keys<T: 'static + BenchTpsClient + Send + Sync>(
         println!("loop not empty: {}", not_funded.len());
         let mut new_funded: Vec<&Keypair> = vec![];
         let mut to_fund: Vec<(&Keypair, Vec<(Pubkey, u64)>)> = vec![];
-        let to_lamports = (funded_funds - lamports_per_account - max_fee) / MAX_SPENDS_PER_TX;
+        // Each funder keeps its own lamports_per_account and pays the fee of its transfer
+        let need = lamports_per_account + max_fee;
+        let to_lamports =
+            funded_funds
+                .checked_sub(need)
+                .ok_or(BenchTpsError::InsufficientFunds {
+                    have: funded_funds,
+                    need,
+                })?
+                / MAX_SPENDS_PER_TX;
         for f in funded {
             println!(
                 "not funded len {}, to lamports {}",
@@ -307,13 +925,35 @@ pub fn fund_keys<T: 'static + BenchTpsClient + Send + Sync>(
         //  assume 4MB network buffers, and 512 byte packets
         const FUND_CHUNK_LEN: usize = 4 * 1024 * 1024 / 512;
 
-        to_fund.chunks(FUND_CHUNK_LEN).for_each(|chunk| {
-            Vec::<(&Keypair, Transaction)>::with_capacity(chunk.len()).fund(
-                &client,
-                chunk,
-                to_lamports,
-            );
-        });
+        for (i, chunk) in to_fund.chunks(FUND_CHUNK_LEN).enumerate() {
+            Vec::<(&Keypair, Transaction)>::with_capacity(chunk.len())
+                .fund(
+                    &client,
+                    chunk,
+                    to_lamports,
+                    compute_budget,
+                    blockhash_service.as_ref(),
+                )
+                .map_err(|err| match err {
+                    // Neither this level's later chunks nor the levels below it got funded
+                    BenchTpsError::FundingIncomplete { remaining } => {
+                        let later_funders = to_fund.len().saturating_sub((i + 1) * FUND_CHUNK_LEN);
+                        BenchTpsError::FundingIncomplete {
+                            remaining: remaining
+                                + later_funders * MAX_SPENDS_PER_TX as usize
+                                + not_funded.len(),
+                        }
+                    }
+                    err => err,
+                })?;
+        }
+
+        if use_durable_nonce {
+            create_nonce_accounts(&client, &new_funded, compute_budget)?;
+            if let Some(blockhash_service) = blockhash_service.take() {
+                blockhash_service.shutdown()?;
+            }
+        }
 
         info!("funded: {} left: {}", new_funded.len(), not_funded.len());
         funded = new_funded;
@@ -324,39 +964,68 @@ pub fn fund_keys<T: 'static + BenchTpsClient + Send + Sync>(
             &not_funded.len()
         );
     }
+    match blockhash_service {
+        Some(blockhash_service) => blockhash_service.shutdown(),
+        None => Ok(()),
+    }
 }
 
-pub fn generate_keypairs(seed_keypair: &Keypair, count: u64) -> (Vec<Keypair>, u64) {
-    let mut seed = [0u8; 32];
-    seed.copy_from_slice(&seed_keypair.to_bytes()[..32]);
-    let mut rnd = GenKeys::new(seed);
-
+/// Number of keypairs, and how many of them are extras that relay lamports to the others,
+/// needed for `fund_keys` to fund `count` keypairs
+pub fn funding_tree_size(count: u64) -> (u64, u64) {
     let mut total_keys = 0;
     let mut extra = 0; // This variable tracks the number of keypairs needing extra transaction fees funded
     let mut delta = 1;
-    println!(
-        "extra {} delta {} total keys {} count {}",
-        extra, delta, total_keys, count
-    );
     while total_keys < count {
         extra += delta;
         delta *= MAX_SPENDS_PER_TX;
         total_keys += delta;
-        println!(
-            "extra {} delta {} total keys {} count {}",
-            extra, delta, total_keys, count
-        );
     }
+    debug!(
+        "funding {} keypairs takes {} keys, {} of them extras",
+        count, total_keys, extra
+    );
+    (total_keys, extra)
+}
+
+/// Seed the keypairs of a run are derived from. Without a namespace it's the funding
+/// keypair's secret, as it has always been, so existing keypairs can still be found.
+pub fn keypair_seed(seed_keypair: &Keypair, namespace: Option<&str>) -> [u8; 32] {
+    let secret = &seed_keypair.to_bytes()[..32];
+    let mut seed = [0u8; 32];
+    match namespace {
+        Some(namespace) => {
+            seed.copy_from_slice(hashv(&[secret, b"namespace", namespace.as_bytes()]).as_ref())
+        }
+        None => seed.copy_from_slice(secret),
+    }
+    seed
+}
+
+/// Derive the keypairs for `count` accounts, and the extras that relay funding to them, from
+/// `seed_keypair` and `namespace`
+pub fn generate_keypairs(
+    seed_keypair: &Keypair,
+    count: u64,
+    namespace: Option<&str>,
+) -> (Vec<Keypair>, u64) {
+    let mut rnd = GenKeys::new(keypair_seed(seed_keypair, namespace));
 
+    let (total_keys, extra) = funding_tree_size(count);
     // total_keys instead of count
     (rnd.gen_n_keypairs(total_keys), extra)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn generate_and_fund_keypairs<T: 'static + BenchTpsClient + Send + Sync>(
     client: Arc<T>,
     funding_key: &Keypair,
     keypair_count: usize,
     lamports_per_account: u64,
+    compute_budget: &ComputeBudget,
+    use_durable_nonce: bool,
+    keypair_store: Option<&Path>,
+    namespace: Option<&str>,
 ) -> Result<Vec<Keypair>> {
     let rent = client.get_minimum_balance_for_rent_exemption(0)?;
     let lamports_per_account = lamports_per_account + rent;
@@ -364,7 +1033,8 @@ pub fn generate_and_fund_keypairs<T: 'static + BenchTpsClient + Send + Sync>(
     let funding_key_balance = client.get_balance(&funding_key.pubkey()).unwrap_or(0);
 
     println!("Creating {} keypairs...", keypair_count);
-    let (mut keypairs, extra) = generate_keypairs(funding_key, keypair_count as u64);
+    let (mut keypairs, extra) =
+        load_or_generate_keypairs(funding_key, keypair_count, namespace, keypair_store)?;
 
     for k in &keypairs {
         println!("key {}", k.pubkey())
@@ -382,7 +1052,18 @@ pub fn generate_and_fund_keypairs<T: 'static + BenchTpsClient + Send + Sync>(
         funding_key_balance
     );
 
-    fund_keypairs(client, funding_key, &keypairs, extra, lamports_per_account)?;
+    fund_keypairs(
+        client,
+        funding_key,
+        &keypairs,
+        extra,
+        lamports_per_account,
+        compute_budget,
+        use_durable_nonce,
+    )?;
+    if let Some(keypair_store) = keypair_store {
+        record_funding(keypair_store, lamports_per_account, use_durable_nonce)?;
+    }
 
     // 'generate_keypairs' generates extra keys to be able to have size-aligned funding batches for fund_keys.
     keypairs.truncate(keypair_count);
@@ -396,6 +1077,8 @@ pub fn fund_keypairs<T: 'static + BenchTpsClient + Send + Sync>(
     keypairs: &[Keypair],
     extra: u64,
     lamports_per_account: u64,
+    compute_budget: &ComputeBudget,
+    use_durable_nonce: bool,
 ) -> Result<()> {
     let rent = client.get_minimum_balance_for_rent_exemption(0)?;
     println!("Get lamports...");
@@ -423,18 +1106,34 @@ pub fn fund_keypairs<T: 'static + BenchTpsClient + Send + Sync>(
     //   pay for the transaction fees in a new run.
 
     let enough_lamports = 8 * lamports_per_account / 10;
-    if first_keypair_balance < enough_lamports || last_keypair_balance < enough_lamports {
+    // Keypairs funded by a run without durable nonces don't have nonce accounts yet
+    let missing_nonce_account = use_durable_nonce && {
+        let last_nonce = nonce_keypair(&keypairs[keypairs.len() - 1]).pubkey();
+        get_nonce_accounts(client.as_ref(), &[last_nonce])?[0].is_none()
+    };
+    if first_keypair_balance < enough_lamports
+        || last_keypair_balance < enough_lamports
+        || missing_nonce_account
+    {
         println!("\n\nfunding keys!!!");
-        let single_sig_message = Message::new_with_blockhash(
-            &[Instruction::new_with_bytes(
-                Pubkey::new_unique(),
-                &[],
-                vec![AccountMeta::new(Pubkey::new_unique(), true)],
-            )],
-            None,
-            &client.get_latest_blockhash().unwrap(),
-        );
-        let max_fee = client.get_fee_for_message(&single_sig_message).unwrap();
+        // The funding transactions carry the compute budget instructions, so any priority fee
+        // is part of what each of them costs
+        let mut instructions = compute_budget.initial_instructions();
+        instructions.push(Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[],
+            vec![AccountMeta::new(Pubkey::new_unique(), true)],
+        ));
+        let single_sig_message =
+            Message::new_with_blockhash(&instructions, None, &client.get_latest_blockhash()?);
+        let max_fee = client.get_fee_for_message(&single_sig_message)?;
+        // Every derived keypair also pays the rent of its nonce account and the two signature
+        // fee of creating it. The funding keypair keeps its share, having no nonce account.
+        let lamports_per_account = if use_durable_nonce {
+            lamports_per_account + nonce_rent(client.as_ref())? + 2 * max_fee
+        } else {
+            lamports_per_account
+        };
         let extra_fees = extra * max_fee;
         let total_keypairs = keypairs.len() as u64 + 1; // Add one for funding keypair
         let total = lamports_per_account * total_keypairs + extra_fees;
@@ -453,16 +1152,16 @@ pub fn fund_keypairs<T: 'static + BenchTpsClient + Send + Sync>(
             );
             let latest_blockhash = get_latest_blockhash(client.as_ref());
 
-            if client
-                .request_airdrop_with_blockhash(
-                    &funding_key.pubkey(),
-                    total + rent - funding_key_balance,
-                    &latest_blockhash,
-                )
-                .is_err()
-            {
-                println!("benchtps airdrop error");
-                return Err(BenchTpsError::AirdropFailure);
+            if let Err(err) = client.request_airdrop_with_blockhash(
+                &funding_key.pubkey(),
+                total + rent - funding_key_balance,
+                &latest_blockhash,
+            ) {
+                println!("benchtps airdrop error: {}", err);
+                return Err(BenchTpsError::InsufficientFunds {
+                    have: funding_key_balance,
+                    need: total + rent,
+                });
             }
         }
         fund_keys(
@@ -472,10 +1171,238 @@ pub fn fund_keypairs<T: 'static + BenchTpsClient + Send + Sync>(
             total,
             max_fee,
             lamports_per_account,
-        );
+            compute_budget,
+            use_durable_nonce,
+        )?;
     } else {
         println!("\n\nnot funding");
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::bench_tps_client::mock_client::{MockCall, MockClient},
+        solana_runtime::{bank::Bank, bank_client::BankClient},
+        solana_sdk::{genesis_config::create_genesis_config, native_token::sol_to_lamports},
+    };
+
+    #[test]
+    fn test_generate_keypairs_namespaces() {
+        let id = Keypair::new();
+        let pubkeys = |namespace| -> Vec<Pubkey> {
+            let (keypairs, _extra) = generate_keypairs(&id, 4, namespace);
+            keypairs.iter().map(|k| k.pubkey()).collect()
+        };
+
+        // No namespace keeps deriving the keypairs earlier runs funded
+        let mut legacy = GenKeys::new(id.to_bytes()[..32].try_into().unwrap());
+        let legacy: Vec<Pubkey> = legacy
+            .gen_n_keypairs(funding_tree_size(4).0)
+            .iter()
+            .map(|k| k.pubkey())
+            .collect();
+        assert_eq!(pubkeys(None), legacy);
+
+        assert_eq!(pubkeys(Some("alice")), pubkeys(Some("alice")));
+        assert_ne!(pubkeys(Some("alice")), pubkeys(Some("bob")));
+        assert_ne!(pubkeys(Some("alice")), pubkeys(None));
+    }
+
+    #[test]
+    fn test_bench_tps_fund_keys() {
+        let (genesis_config, id) = create_genesis_config(sol_to_lamports(10_000.0));
+        let bank = Bank::new_for_tests(&genesis_config);
+        let client = Arc::new(BankClient::new(bank));
+        let keypair_count = 20;
+        let lamports = 20;
+        let rent = client.get_minimum_balance_for_rent_exemption(0).unwrap();
+
+        let keypairs = generate_and_fund_keypairs(
+            client.clone(),
+            &id,
+            keypair_count,
+            lamports,
+            &ComputeBudget::default(),
+            false,
+            None,
+            None,
+        )
+        .unwrap();
+
+        for kp in &keypairs {
+            assert_eq!(
+                client
+                    .get_balance_with_commitment(&kp.pubkey(), CommitmentConfig::processed())
+                    .unwrap(),
+                lamports + rent
+            );
+        }
+    }
+
+    #[test]
+    fn test_bench_tps_fund_keys_with_durable_nonces() {
+        let (genesis_config, id) = create_genesis_config(sol_to_lamports(10_000.0));
+        let bank = Bank::new_for_tests(&genesis_config);
+        let client = Arc::new(BankClient::new(bank));
+
+        // A single level, as the bank never moves on from the blockhash the nonces store
+        let keypairs = generate_and_fund_keypairs(
+            client.clone(),
+            &id,
+            MAX_SPENDS_PER_TX,
+            20,
+            &ComputeBudget::default(),
+            true,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let nonce_pubkeys: Vec<Pubkey> =
+            keypairs.iter().map(|k| nonce_keypair(k).pubkey()).collect();
+        let nonces = get_nonce_accounts(client.as_ref(), &nonce_pubkeys).unwrap();
+        assert!(nonces.iter().all(Option::is_some));
+        // Nothing would ever close a nonce account of the funding keypair
+        let funder_nonce = nonce_keypair(&id).pubkey();
+        assert!(get_nonce_accounts(client.as_ref(), &[funder_nonce]).unwrap()[0].is_none());
+    }
+
+    const LAMPORTS_PER_ACCOUNT: u64 = 1_000_000;
+    // What MockClient charges a single signature transaction
+    const MOCK_FEE: u64 = 5000;
+
+    /// Keypairs to fund from `id`, and what `fund_keys` needs to fund all of them
+    fn funding_tree(id: &Keypair, count: u64) -> (Vec<Keypair>, u64) {
+        let (keypairs, extra) = generate_keypairs(id, count, None);
+        let total = LAMPORTS_PER_ACCOUNT * (keypairs.len() as u64 + 1) + extra * MOCK_FEE;
+        (keypairs, total)
+    }
+
+    #[test]
+    fn test_fund_keys_retries_dropped_transfers() {
+        let client = Arc::new(MockClient::default().with_drop_rate(0.5));
+        let id = Keypair::new();
+        let (keypairs, total) = funding_tree(&id, 20);
+        client.set_balance(&id.pubkey(), total);
+
+        fund_keys(
+            client.clone(),
+            &id,
+            &keypairs,
+            total,
+            MOCK_FEE,
+            LAMPORTS_PER_ACCOUNT,
+            &ComputeBudget::default(),
+            false,
+        )
+        .unwrap();
+
+        assert!(client.dropped() > 0);
+        // One batch for each of the two levels of the tree, and more for the retries
+        assert!(client.call_count(MockCall::SendBatch) > 2);
+        for keypair in &keypairs {
+            assert_eq!(client.balance(&keypair.pubkey()), LAMPORTS_PER_ACCOUNT);
+        }
+    }
+
+    #[test]
+    fn test_fund_keys_failures() {
+        let client = Arc::new(MockClient::default());
+        let id = Keypair::new();
+        let (keypairs, total) = funding_tree(&id, 20);
+        client.set_balance(&id.pubkey(), total);
+        let fund = |total| {
+            fund_keys(
+                client.clone(),
+                &id,
+                &keypairs,
+                total,
+                MOCK_FEE,
+                LAMPORTS_PER_ACCOUNT,
+                &ComputeBudget::default(),
+                false,
+            )
+        };
+
+        client.fail_next(MockCall::SendBatch, 1);
+        assert!(matches!(fund(total), Err(BenchTpsError::Custom(_))));
+        assert_eq!(client.balance(&id.pubkey()), total);
+
+        // Not even the first level can keep its own lamports
+        assert!(matches!(
+            fund(LAMPORTS_PER_ACCOUNT),
+            Err(BenchTpsError::InsufficientFunds { .. })
+        ));
+        assert_eq!(total_balance(&client, &keypairs), 0);
+    }
+
+    #[test]
+    fn test_fund_keypairs_skips_funded_keypairs() {
+        let client = Arc::new(MockClient::default());
+        let id = Keypair::new();
+        let (keypairs, extra) = generate_keypairs(&id, 20, None);
+        let enough_lamports = 8 * LAMPORTS_PER_ACCOUNT / 10;
+        for keypair in &keypairs {
+            client.set_balance(&keypair.pubkey(), enough_lamports);
+        }
+        let fund = || {
+            fund_keypairs(
+                client.clone(),
+                &id,
+                &keypairs,
+                extra,
+                LAMPORTS_PER_ACCOUNT,
+                &ComputeBudget::default(),
+                false,
+            )
+        };
+
+        // With 80% of their lamports left, the keypairs can run again as they are
+        fund().unwrap();
+        assert_eq!(client.call_count(MockCall::SendBatch), 0);
+        assert_eq!(client.call_count(MockCall::RequestAirdrop), 0);
+
+        client.set_balance(&id.pubkey(), 100 * LAMPORTS_PER_ACCOUNT);
+        client.set_balance(&keypairs[keypairs.len() - 1].pubkey(), enough_lamports - 1);
+        fund().unwrap();
+        assert!(client.call_count(MockCall::SendBatch) > 0);
+        for keypair in &keypairs {
+            assert!(client.balance(&keypair.pubkey()) >= LAMPORTS_PER_ACCOUNT);
+        }
+    }
+
+    #[test]
+    fn test_fund_keypairs_airdrops_shortfall() {
+        let client = Arc::new(MockClient::default());
+        let id = Keypair::new();
+        let (keypairs, extra) = generate_keypairs(&id, 20, None);
+        let fund = || {
+            fund_keypairs(
+                client.clone(),
+                &id,
+                &keypairs,
+                extra,
+                LAMPORTS_PER_ACCOUNT,
+                &ComputeBudget::default(),
+                false,
+            )
+        };
+
+        client.fail_next(MockCall::RequestAirdrop, 1);
+        assert!(matches!(
+            fund(),
+            Err(BenchTpsError::InsufficientFunds { have: 0, .. })
+        ));
+        assert_eq!(client.call_count(MockCall::SendBatch), 0);
+
+        fund().unwrap();
+        assert_eq!(client.call_count(MockCall::RequestAirdrop), 2);
+        for keypair in &keypairs {
+            assert!(client.balance(&keypair.pubkey()) >= LAMPORTS_PER_ACCOUNT);
+        }
+    }
+}
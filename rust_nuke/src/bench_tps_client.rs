@@ -1,9 +1,11 @@
 use {
     solana_client::{client_error::ClientError, tpu_client::TpuSenderError},
     solana_sdk::{
-        commitment_config::CommitmentConfig, epoch_info::EpochInfo, hash::Hash, message::Message,
-        pubkey::Pubkey, signature::Signature, transaction::Transaction, transport::TransportError,
+        account::Account, commitment_config::CommitmentConfig, epoch_info::EpochInfo, hash::Hash,
+        message::Message, pubkey::Pubkey, signature::Signature, transaction::Transaction,
+        transport::TransportError,
     },
+    solana_transaction_status::TransactionStatus,
     thiserror::Error,
 };
 
@@ -21,6 +23,14 @@ pub enum BenchTpsError {
     TransportError(#[from] TransportError),
     #[error("Custom error: {0}")]
     Custom(String),
+    #[error("Blockhash is stuck")]
+    BlockhashStuck,
+    #[error("Funding incomplete, {remaining} accounts left")]
+    FundingIncomplete { remaining: usize },
+    #[error("{missing} nonce accounts never appeared")]
+    NonceAccountsMissing { missing: usize },
+    #[error("Insufficient funds: have {have} lamports, need {need}")]
+    InsufficientFunds { have: u64, need: u64 },
 }
 
 pub(crate) type Result<T> = std::result::Result<T, BenchTpsError>;
@@ -72,6 +82,15 @@ pub trait BenchTpsClient {
     /// Return the address of client
     fn addr(&self) -> String;
 
+    /// Get the statuses of a batch of signatures, `None` for signatures the node hasn't seen
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> Result<Vec<Option<TransactionStatus>>>;
+
+    /// Get a batch of accounts, `None` for accounts that don't exist
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>>;
+
     /// Request, submit, and confirm an airdrop transaction
     fn request_airdrop_with_blockhash(
         &self,
@@ -81,7 +100,9 @@ pub trait BenchTpsClient {
     ) -> Result<Signature>;
 }
 
-// mod bank_client;
+mod bank_client;
+#[cfg(test)]
+pub(crate) mod mock_client;
 mod rpc_client;
-// mod thin_client;
-// mod tpu_client;
+mod thin_client;
+mod tpu_client;
@@ -0,0 +1,345 @@
+use {
+    crate::bench_tps_client::{BenchTpsClient, BenchTpsError, Result},
+    solana_sdk::{
+        account::Account,
+        clock::Slot,
+        commitment_config::CommitmentConfig,
+        epoch_info::EpochInfo,
+        hash::{hashv, Hash},
+        instruction::InstructionError,
+        message::Message,
+        pubkey::Pubkey,
+        signature::Signature,
+        system_instruction::{SystemError, SystemInstruction},
+        system_program,
+        transaction::{self, Transaction, TransactionError},
+    },
+    solana_transaction_status::{TransactionConfirmationStatus, TransactionStatus},
+    std::{
+        collections::HashMap,
+        sync::{Mutex, MutexGuard},
+        thread::sleep,
+        time::Duration,
+    },
+};
+
+/// `BenchTpsClient` methods, as `MockClient` records them and injects errors into. The
+/// `_with_commitment` variants count as the method they refine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum MockCall {
+    SendTransaction,
+    SendBatch,
+    GetLatestBlockhash,
+    GetTransactionCount,
+    GetEpochInfo,
+    GetBalance,
+    GetFeeForMessage,
+    GetMinimumBalanceForRentExemption,
+    GetSignatureStatuses,
+    GetMultipleAccounts,
+    RequestAirdrop,
+}
+
+#[derive(Default)]
+struct MockState {
+    balances: HashMap<Pubkey, u64>,
+    statuses: HashMap<Signature, transaction::Result<()>>,
+    // Every blockhash request moves on a slot, so callers waiting for a new one never wait
+    slot: Slot,
+    transaction_count: u64,
+    // Builds up by the drop rate with every transaction, one is dropped each time it's full
+    drop_credit: f64,
+    dropped: usize,
+    failures: HashMap<MockCall, usize>,
+    calls: Vec<MockCall>,
+}
+
+/// In-memory `BenchTpsClient` that keeps balances in a map and applies system transfers as
+/// soon as they're sent. Transactions are dropped at a fixed rate, so tests stay
+/// deterministic.
+pub(crate) struct MockClient {
+    lamports_per_signature: u64,
+    rent: u64,
+    drop_rate: f64,
+    latency: Duration,
+    state: Mutex<MockState>,
+}
+
+impl Default for MockClient {
+    fn default() -> Self {
+        Self {
+            lamports_per_signature: 5000,
+            rent: 890_880,
+            drop_rate: 0.0,
+            latency: Duration::ZERO,
+            state: Mutex::default(),
+        }
+    }
+}
+
+impl MockClient {
+    /// Drop this share of the transactions sent, without a status
+    pub fn with_drop_rate(self, drop_rate: f64) -> Self {
+        Self { drop_rate, ..self }
+    }
+
+    /// Wait this long before answering every call
+    pub fn with_latency(self, latency: Duration) -> Self {
+        Self { latency, ..self }
+    }
+
+    /// Fail the next `count` calls of `call`
+    pub fn fail_next(&self, call: MockCall, count: usize) {
+        self.state.lock().unwrap().failures.insert(call, count);
+    }
+
+    pub fn set_balance(&self, pubkey: &Pubkey, lamports: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .balances
+            .insert(*pubkey, lamports);
+    }
+
+    pub fn balance(&self, pubkey: &Pubkey) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.balances.get(pubkey).copied().unwrap_or(0)
+    }
+
+    /// Every call made so far, in order
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    pub fn call_count(&self, call: MockCall) -> usize {
+        let state = self.state.lock().unwrap();
+        state.calls.iter().filter(|c| **c == call).count()
+    }
+
+    /// Transactions dropped so far
+    pub fn dropped(&self) -> usize {
+        self.state.lock().unwrap().dropped
+    }
+
+    /// Record `call` and fail it if it's been told to
+    fn call(&self, call: MockCall) -> Result<MutexGuard<MockState>> {
+        if !self.latency.is_zero() {
+            sleep(self.latency);
+        }
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(call);
+        if let Some(failures) = state
+            .failures
+            .get_mut(&call)
+            .filter(|failures| **failures > 0)
+        {
+            *failures -= 1;
+            return Err(BenchTpsError::Custom(format!(
+                "injected {:?} failure",
+                call
+            )));
+        }
+        Ok(state)
+    }
+
+    fn fee(&self, message: &Message) -> u64 {
+        self.lamports_per_signature * message.header.num_required_signatures as u64
+    }
+
+    fn blockhash(state: &mut MockState) -> (Hash, u64) {
+        state.slot += 1;
+        (hashv(&[&state.slot.to_le_bytes()[..]]), state.slot + 150)
+    }
+
+    /// Charge the fee and apply the transfers of `transaction` if it isn't dropped. Like a
+    /// bank, a failed transaction still pays its fee, and one that can't doesn't land.
+    fn process(&self, state: &mut MockState, transaction: &Transaction) {
+        state.drop_credit += self.drop_rate;
+        if state.drop_credit >= 1.0 {
+            state.drop_credit -= 1.0;
+            state.dropped += 1;
+            return;
+        }
+        let signature = transaction.signatures[0];
+        if transaction.verify().is_err() || state.statuses.contains_key(&signature) {
+            return;
+        }
+        let message = transaction.message();
+        let payer = message.account_keys[0];
+        let payer_balance = match state.balances.get(&payer) {
+            Some(balance) if *balance >= self.fee(message) => *balance - self.fee(message),
+            _ => return,
+        };
+        state.balances.insert(payer, payer_balance);
+
+        let mut changed = HashMap::new();
+        let result =
+            message
+                .instructions
+                .iter()
+                .enumerate()
+                .try_for_each(|(index, instruction)| {
+                    let program_id = message.account_keys[instruction.program_id_index as usize];
+                    if program_id != system_program::id() {
+                        // Compute budget instructions only matter to fees, which are flat here
+                        return Ok(());
+                    }
+                    let error = |err| TransactionError::InstructionError(index as u8, err);
+                    let account = |i: usize| message.account_keys[instruction.accounts[i] as usize];
+                    match bincode::deserialize(&instruction.data) {
+                        Ok(SystemInstruction::Transfer { lamports }) => {
+                            let balance = |pubkey: &Pubkey, changed: &HashMap<Pubkey, u64>| {
+                                changed
+                                    .get(pubkey)
+                                    .or_else(|| state.balances.get(pubkey))
+                                    .copied()
+                                    .unwrap_or(0)
+                            };
+                            let from_balance = balance(&account(0), &changed)
+                                .checked_sub(lamports)
+                                .ok_or_else(|| {
+                                    error(InstructionError::Custom(
+                                        SystemError::ResultWithNegativeLamports as u32,
+                                    ))
+                                })?;
+                            changed.insert(account(0), from_balance);
+                            let to_balance = balance(&account(1), &changed) + lamports;
+                            changed.insert(account(1), to_balance);
+                            Ok(())
+                        }
+                        _ => Err(error(InstructionError::InvalidInstructionData)),
+                    }
+                });
+        if result.is_ok() {
+            state.balances.extend(changed);
+        }
+        state.statuses.insert(signature, result);
+        state.transaction_count += 1;
+    }
+}
+
+impl BenchTpsClient for MockClient {
+    fn send_transaction(&self, transaction: Transaction) -> Result<Signature> {
+        let mut state = self.call(MockCall::SendTransaction)?;
+        self.process(&mut state, &transaction);
+        Ok(transaction.signatures[0])
+    }
+
+    fn send_batch(&self, transactions: Vec<Transaction>) -> Result<()> {
+        let mut state = self.call(MockCall::SendBatch)?;
+        for transaction in &transactions {
+            self.process(&mut state, transaction);
+        }
+        Ok(())
+    }
+
+    fn get_latest_blockhash(&self) -> Result<Hash> {
+        let mut state = self.call(MockCall::GetLatestBlockhash)?;
+        Ok(Self::blockhash(&mut state).0)
+    }
+
+    fn get_latest_blockhash_with_commitment(
+        &self,
+        _commitment_config: CommitmentConfig,
+    ) -> Result<(Hash, u64)> {
+        let mut state = self.call(MockCall::GetLatestBlockhash)?;
+        Ok(Self::blockhash(&mut state))
+    }
+
+    fn get_transaction_count(&self) -> Result<u64> {
+        Ok(self.call(MockCall::GetTransactionCount)?.transaction_count)
+    }
+
+    fn get_transaction_count_with_commitment(
+        &self,
+        _commitment_config: CommitmentConfig,
+    ) -> Result<u64> {
+        self.get_transaction_count()
+    }
+
+    fn get_epoch_info(&self) -> Result<EpochInfo> {
+        let state = self.call(MockCall::GetEpochInfo)?;
+        Ok(EpochInfo {
+            epoch: 0,
+            slot_index: state.slot,
+            slots_in_epoch: 432_000,
+            absolute_slot: state.slot,
+            block_height: state.slot,
+            transaction_count: Some(state.transaction_count),
+        })
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> Result<u64> {
+        let state = self.call(MockCall::GetBalance)?;
+        Ok(state.balances.get(pubkey).copied().unwrap_or(0))
+    }
+
+    fn get_balance_with_commitment(
+        &self,
+        pubkey: &Pubkey,
+        _commitment_config: CommitmentConfig,
+    ) -> Result<u64> {
+        self.get_balance(pubkey)
+    }
+
+    fn get_fee_for_message(&self, message: &Message) -> Result<u64> {
+        self.call(MockCall::GetFeeForMessage)?;
+        Ok(self.fee(message))
+    }
+
+    fn get_minimum_balance_for_rent_exemption(&self, _data_len: usize) -> Result<u64> {
+        self.call(MockCall::GetMinimumBalanceForRentExemption)?;
+        Ok(self.rent)
+    }
+
+    fn addr(&self) -> String {
+        "MockClient".to_string()
+    }
+
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> Result<Vec<Option<TransactionStatus>>> {
+        let state = self.call(MockCall::GetSignatureStatuses)?;
+        Ok(signatures
+            .iter()
+            .map(|signature| {
+                state
+                    .statuses
+                    .get(signature)
+                    .map(|status| TransactionStatus {
+                        slot: state.slot,
+                        confirmations: None,
+                        err: status.clone().err(),
+                        status: status.clone(),
+                        confirmation_status: Some(TransactionConfirmationStatus::Finalized),
+                    })
+            })
+            .collect())
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let state = self.call(MockCall::GetMultipleAccounts)?;
+        Ok(pubkeys
+            .iter()
+            .map(|pubkey| {
+                state
+                    .balances
+                    .get(pubkey)
+                    .filter(|lamports| **lamports > 0)
+                    .map(|lamports| Account::new(*lamports, 0, &system_program::id()))
+            })
+            .collect())
+    }
+
+    fn request_airdrop_with_blockhash(
+        &self,
+        pubkey: &Pubkey,
+        lamports: u64,
+        _recent_blockhash: &Hash,
+    ) -> Result<Signature> {
+        let mut state = self.call(MockCall::RequestAirdrop)?;
+        *state.balances.entry(*pubkey).or_default() += lamports;
+        Ok(Signature::default())
+    }
+}
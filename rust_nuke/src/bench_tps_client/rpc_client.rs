@@ -2,9 +2,10 @@ use {
     crate::bench_tps_client::{BenchTpsClient, Result},
     solana_client::rpc_client::RpcClient,
     solana_sdk::{
-        commitment_config::CommitmentConfig, epoch_info::EpochInfo, hash::Hash, message::Message,
-        pubkey::Pubkey, signature::Signature, transaction::Transaction,
+        account::Account, commitment_config::CommitmentConfig, epoch_info::EpochInfo, hash::Hash,
+        message::Message, pubkey::Pubkey, signature::Signature, transaction::Transaction,
     },
+    solana_transaction_status::TransactionStatus,
 };
 
 impl BenchTpsClient for RpcClient {
@@ -71,6 +72,19 @@ impl BenchTpsClient for RpcClient {
         self.url()
     }
 
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> Result<Vec<Option<TransactionStatus>>> {
+        RpcClient::get_signature_statuses(self, signatures)
+            .map(|res| res.value)
+            .map_err(|err| err.into())
+    }
+
+    fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        RpcClient::get_multiple_accounts(self, pubkeys).map_err(|err| err.into())
+    }
+
     fn request_airdrop_with_blockhash(
         &self,
         pubkey: &Pubkey,
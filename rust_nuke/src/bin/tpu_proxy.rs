@@ -0,0 +1,164 @@
+//! Accepts `sendTransaction` JSON-RPC requests over HTTP and forwards the transactions to the
+//! TPUs of the upcoming leaders over UDP, for clients that can't send UDP themselves
+
+use {
+    clap::{crate_version, App, Arg},
+    rust_nuke::{
+        leader::LeaderTracker,
+        proxy::{TpuProxy, TpuTargets, UpcomingLeaders},
+    },
+    solana_clap_utils::input_validators::{is_host_port, is_parsable, is_url_or_moniker},
+    solana_cli_config::ConfigInput,
+    solana_client::rpc_client::RpcClient,
+    std::{
+        net::SocketAddr,
+        process::exit,
+        sync::Arc,
+        thread::{sleep, Builder},
+        time::Duration,
+    },
+    tiny_http::Server,
+};
+
+fn run<T: 'static + TpuTargets>(proxy: TpuProxy<T>, server: Server, threads: usize, interval: u64) {
+    let proxy = Arc::new(proxy);
+    let server = Arc::new(server);
+    let _workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let proxy = proxy.clone();
+            let server = server.clone();
+            Builder::new()
+                .name("solana-tpu-proxy".to_string())
+                .spawn(move || proxy.serve(&server))
+                .unwrap()
+        })
+        .collect();
+
+    loop {
+        sleep(Duration::from_secs(interval));
+        let mut stats: Vec<_> = proxy.submitter_stats().into_iter().collect();
+        stats.sort_by_key(|(submitter, _)| *submitter);
+        for (submitter, stats) in stats {
+            println!(
+                "{}: received {}, forwarded {}, rejected {}, packets {}",
+                submitter, stats.received, stats.forwarded, stats.rejected, stats.packets
+            );
+        }
+    }
+}
+
+fn main() {
+    solana_logger::setup_with_default("solana=info");
+
+    let matches = App::new("tpu_proxy")
+        .about("Forward sendTransaction requests to the TPUs of upcoming leaders")
+        .version(crate_version!())
+        .arg(
+            Arg::with_name("json_rpc_url")
+                .short("u")
+                .long("url")
+                .value_name("URL_OR_MONIKER")
+                .takes_value(true)
+                .validator(is_url_or_moniker)
+                .help("JSON RPC endpoint the leader schedule and cluster nodes are read from"),
+        )
+        .arg(
+            Arg::with_name("websocket_url")
+                .long("ws")
+                .value_name("URL")
+                .takes_value(true)
+                .validator(is_url_or_moniker)
+                .help("Websocket endpoint slot updates come from. Default: derived from --url"),
+        )
+        .arg(
+            Arg::with_name("bind")
+                .long("bind")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .default_value("127.0.0.1:8080")
+                .validator(is_host_port)
+                .help("Address to accept JSON-RPC requests on"),
+        )
+        .arg(
+            Arg::with_name("fanout")
+                .long("fanout")
+                .value_name("LEADERS")
+                .takes_value(true)
+                .default_value("4")
+                .validator(is_parsable::<usize>)
+                .help("Number of upcoming leaders each transaction is forwarded to"),
+        )
+        .arg(
+            Arg::with_name("tpu_addr")
+                .long("tpu-addr")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .multiple(true)
+                .validator(is_host_port)
+                .help("Forward to these TPUs instead of following the leader schedule"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .value_name("THREADS")
+                .takes_value(true)
+                .default_value("4")
+                .validator(is_parsable::<usize>)
+                .help("Number of threads answering requests"),
+        )
+        .arg(
+            Arg::with_name("stats_interval")
+                .long("stats-interval")
+                .value_name("SECS")
+                .takes_value(true)
+                .default_value("10")
+                .validator(is_parsable::<u64>)
+                .help("How often to print the per-submitter counters"),
+        )
+        .get_matches();
+
+    // The validators above make these parses infallible
+    let bind = solana_net_utils::parse_host_port(matches.value_of("bind").unwrap()).unwrap();
+    let fanout = matches.value_of("fanout").unwrap().parse().unwrap();
+    let threads = matches.value_of("threads").unwrap().parse().unwrap();
+    let interval = matches.value_of("stats_interval").unwrap().parse().unwrap();
+
+    let server = Server::http(bind).unwrap_or_else(|err| {
+        eprintln!("Could not listen on {}: {}", bind, err);
+        exit(1);
+    });
+    println!("accepting transactions on http://{}", bind);
+
+    if let Some(tpu_addrs) = matches.values_of("tpu_addr") {
+        let tpus: Vec<SocketAddr> = tpu_addrs
+            .map(|addr| solana_net_utils::parse_host_port(addr).unwrap())
+            .collect();
+        let proxy = TpuProxy::new(tpus).unwrap_or_else(|err| {
+            eprintln!("Could not open a UDP socket: {}", err);
+            exit(1);
+        });
+        run(proxy, server, threads, interval);
+    } else {
+        let config = solana_cli_config::Config::default();
+        let (_, json_rpc_url) = ConfigInput::compute_json_rpc_url_setting(
+            matches.value_of("json_rpc_url").unwrap_or(""),
+            &config.json_rpc_url,
+        );
+        let (_, websocket_url) = ConfigInput::compute_websocket_url_setting(
+            matches.value_of("websocket_url").unwrap_or(""),
+            &config.websocket_url,
+            matches.value_of("json_rpc_url").unwrap_or(""),
+            &config.json_rpc_url,
+        );
+        let rpc_client = Arc::new(RpcClient::new(json_rpc_url));
+        let tracker = LeaderTracker::new(rpc_client, &websocket_url).unwrap_or_else(|err| {
+            eprintln!("Could not track leaders: {}", err);
+            exit(1);
+        });
+        let proxy = TpuProxy::new(UpcomingLeaders { tracker, fanout }).unwrap_or_else(|err| {
+            eprintln!("Could not open a UDP socket: {}", err);
+            exit(1);
+        });
+        run(proxy, server, threads, interval);
+    }
+}
@@ -0,0 +1,443 @@
+use {
+    crate::{bench_tps_client::Result, report::ReportFormat},
+    log::*,
+    rayon::prelude::*,
+    serde::Serialize,
+    solana_client::{rpc_client::RpcClient, rpc_config::RpcBlockConfig},
+    solana_sdk::{clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey},
+    solana_transaction_status::{
+        EncodedTransaction, RewardType, TransactionDetails, UiConfirmedBlock, UiMessage,
+        UiTransaction, UiTransactionEncoding,
+    },
+    std::{
+        collections::HashSet,
+        fs::File,
+        io::{self, BufWriter, Write},
+        ops::RangeInclusive,
+        path::Path,
+    },
+};
+
+// Slots whose blocks are listed and fetched at a time. getBlocks rejects ranges much longer
+// than this, and it bounds how many block stats are in flight.
+const SLOTS_PER_CHUNK: u64 = 1_000;
+
+// Blocks fetched concurrently. Each getBlock returns every transaction of the block in full.
+const BLOCK_FETCH_THREADS: usize = 8;
+
+/// What one slot of the range held
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SlotStats {
+    pub slot: Slot,
+    /// No block was produced for the slot
+    pub skipped: bool,
+    pub transactions: usize,
+    /// Transactions paid for by our keypairs
+    pub our_transactions: usize,
+    pub failed: usize,
+    pub compute_units: u64,
+    /// Fees paid by the block's transactions
+    pub fees: u64,
+    /// Fees the leader collected for the block
+    pub leader_fees: u64,
+}
+
+impl SlotStats {
+    pub fn our_share(&self) -> f64 {
+        ratio(self.our_transactions, self.transactions)
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        ratio(self.failed, self.transactions)
+    }
+}
+
+fn ratio(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+/// Per-slot stats over a range of slots, and their totals
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BlockStatsReport {
+    pub start_slot: Slot,
+    pub end_slot: Slot,
+    pub skipped_slots: usize,
+    /// Slots the node couldn't report on: before its first available block, past its
+    /// confirmed slot, or produced blocks it failed to return
+    pub unavailable_slots: usize,
+    pub transactions: usize,
+    pub our_transactions: usize,
+    pub our_share: f64,
+    pub failed: usize,
+    pub failure_rate: f64,
+    pub compute_units: u64,
+    pub fees: u64,
+    pub leader_fees: u64,
+    pub slots: Vec<SlotStats>,
+}
+
+impl BlockStatsReport {
+    fn new(start_slot: Slot, end_slot: Slot, slots: Vec<SlotStats>, unavailable: usize) -> Self {
+        let mut report = Self {
+            start_slot,
+            end_slot,
+            unavailable_slots: unavailable,
+            ..Self::default()
+        };
+        for slot in &slots {
+            report.skipped_slots += slot.skipped as usize;
+            report.transactions += slot.transactions;
+            report.our_transactions += slot.our_transactions;
+            report.failed += slot.failed;
+            report.compute_units += slot.compute_units;
+            report.fees += slot.fees;
+            report.leader_fees += slot.leader_fees;
+        }
+        report.our_share = ratio(report.our_transactions, report.transactions);
+        report.failure_rate = ratio(report.failed, report.transactions);
+        report.slots = slots;
+        report
+    }
+
+    pub fn print(&self) {
+        println!(
+            "slots {}..={}: {} skipped, {} unavailable",
+            self.start_slot, self.end_slot, self.skipped_slots, self.unavailable_slots
+        );
+        println!(
+            "{} transactions, {} ours ({:.1}%), {} failed ({:.1}%)",
+            self.transactions,
+            self.our_transactions,
+            self.our_share * 100.0,
+            self.failed,
+            self.failure_rate * 100.0
+        );
+        println!(
+            "{} compute units, {} lamports of fees, {} collected by leaders",
+            self.compute_units, self.fees, self.leader_fees
+        );
+    }
+
+    /// Write the report to `path`. CSV holds one row per slot, JSON the totals as well.
+    pub fn write(&self, path: &Path, format: ReportFormat) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, self)?;
+                writeln!(writer)
+            }
+            ReportFormat::Csv => {
+                writeln!(
+                    writer,
+                    "slot,skipped,transactions,our_transactions,our_share,failed,failure_rate,\
+                     compute_units,fees,leader_fees"
+                )?;
+                for slot in &self.slots {
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{:.4},{},{:.4},{},{},{}",
+                        slot.slot,
+                        slot.skipped,
+                        slot.transactions,
+                        slot.our_transactions,
+                        slot.our_share(),
+                        slot.failed,
+                        slot.failure_rate(),
+                        slot.compute_units,
+                        slot.fees,
+                        slot.leader_fees
+                    )?;
+                }
+                writer.flush()
+            }
+        }
+    }
+}
+
+/// Compute units consumed by a transaction, from the `consumed N of M compute units` lines of
+/// its top level instructions. Inner instructions are already counted in theirs.
+pub fn compute_units_consumed(log_messages: &[String]) -> u64 {
+    let mut depth = 0;
+    let mut consumed = 0;
+    for message in log_messages {
+        let words: Vec<&str> = message.split_whitespace().collect();
+        match words.as_slice() {
+            ["Program", _, "invoke", level] => {
+                depth = level
+                    .trim_matches(|c| c == '[' || c == ']')
+                    .parse()
+                    .unwrap_or(depth + 1);
+            }
+            ["Program", _, "consumed", units, "of", _, "compute", "units"] if depth == 1 => {
+                consumed += units.parse::<u64>().unwrap_or(0);
+            }
+            ["Program", _, "success"] | ["Program", _, "failed:", ..] => {
+                depth = depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+    }
+    consumed
+}
+
+fn fee_payer(transaction: &EncodedTransaction) -> Option<Pubkey> {
+    match transaction {
+        EncodedTransaction::Json(UiTransaction {
+            message: UiMessage::Raw(message),
+            ..
+        }) => message.account_keys.first()?.parse().ok(),
+        EncodedTransaction::Json(UiTransaction {
+            message: UiMessage::Parsed(message),
+            ..
+        }) => message.account_keys.first()?.pubkey.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Stats of the block produced for `slot`, counting transactions paid for by `our_keys` as ours
+pub fn block_stats(slot: Slot, block: &UiConfirmedBlock, our_keys: &HashSet<Pubkey>) -> SlotStats {
+    let mut stats = SlotStats {
+        slot,
+        ..SlotStats::default()
+    };
+    for transaction in block.transactions.iter().flatten() {
+        stats.transactions += 1;
+        if fee_payer(&transaction.transaction).map_or(false, |payer| our_keys.contains(&payer)) {
+            stats.our_transactions += 1;
+        }
+        if let Some(meta) = &transaction.meta {
+            stats.failed += meta.err.is_some() as usize;
+            stats.fees += meta.fee;
+            stats.compute_units += meta
+                .log_messages
+                .as_deref()
+                .map_or(0, compute_units_consumed);
+        }
+    }
+    stats.leader_fees = block
+        .rewards
+        .iter()
+        .flatten()
+        .filter(|reward| reward.reward_type == Some(RewardType::Fee))
+        .map(|reward| reward.lamports.max(0) as u64)
+        .sum();
+    stats
+}
+
+/// The part of `start_slot..=end_slot` the node can report on, given the first block it holds
+/// and its latest confirmed slot. Whether a slot outside of it was skipped can't be told.
+fn available_slots(
+    start_slot: Slot,
+    end_slot: Slot,
+    first_available_block: Slot,
+    confirmed_slot: Slot,
+) -> RangeInclusive<Slot> {
+    start_slot.max(first_available_block)..=end_slot.min(confirmed_slot)
+}
+
+fn slot_count(slots: &RangeInclusive<Slot>) -> usize {
+    if slots.is_empty() {
+        0
+    } else {
+        (slots.end() - slots.start() + 1) as usize
+    }
+}
+
+/// Split `slots` into consecutive ranges of at most `SLOTS_PER_CHUNK` slots
+fn slot_chunks(slots: RangeInclusive<Slot>) -> impl Iterator<Item = RangeInclusive<Slot>> {
+    let end_slot = *slots.end();
+    slots
+        .step_by(SLOTS_PER_CHUNK as usize)
+        .map(move |start| start..=end_slot.min(start + SLOTS_PER_CHUNK - 1))
+}
+
+/// Fetch every block from `start_slot` through `end_slot` and collect their stats
+pub fn collect_block_stats(
+    rpc_client: &RpcClient,
+    start_slot: Slot,
+    end_slot: Slot,
+    our_keys: &HashSet<Pubkey>,
+) -> Result<BlockStatsReport> {
+    let commitment = CommitmentConfig::confirmed();
+    let available = available_slots(
+        start_slot,
+        end_slot,
+        rpc_client.get_first_available_block()?,
+        rpc_client.get_slot_with_commitment(commitment)?,
+    );
+    let mut unavailable = slot_count(&(start_slot..=end_slot)) - slot_count(&available);
+    if unavailable > 0 {
+        warn!(
+            "The node can only report on slots {}..={} of {}..={}",
+            available.start(),
+            available.end(),
+            start_slot,
+            end_slot
+        );
+    }
+
+    let config = RpcBlockConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        transaction_details: Some(TransactionDetails::Full),
+        rewards: Some(true),
+        commitment: Some(commitment),
+        ..RpcBlockConfig::default()
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(BLOCK_FETCH_THREADS)
+        .thread_name(|i| format!("solana-block-stats-{}", i))
+        .build()
+        .unwrap();
+    let mut slots = vec![];
+    for chunk in slot_chunks(available) {
+        let produced: HashSet<Slot> = rpc_client
+            .get_blocks_with_commitment(*chunk.start(), Some(*chunk.end()), commitment)?
+            .into_iter()
+            .collect();
+        println!(
+            "fetching {} blocks of slots {}..={}",
+            produced.len(),
+            chunk.start(),
+            chunk.end()
+        );
+        let chunk_slots: Vec<Option<SlotStats>> = pool.install(|| {
+            chunk
+                .into_par_iter()
+                .map(|slot| {
+                    if !produced.contains(&slot) {
+                        return Some(SlotStats {
+                            slot,
+                            skipped: true,
+                            ..SlotStats::default()
+                        });
+                    }
+                    match rpc_client.get_block_with_config(slot, config) {
+                        Ok(block) => Some(block_stats(slot, &block, our_keys)),
+                        Err(err) => {
+                            warn!("Couldn't get the block of slot {}: {}", slot, err);
+                            None
+                        }
+                    }
+                })
+                .collect()
+        });
+        unavailable += chunk_slots.iter().filter(|slot| slot.is_none()).count();
+        slots.extend(chunk_slots.into_iter().flatten());
+    }
+    Ok(BlockStatsReport::new(
+        start_slot,
+        end_slot,
+        slots,
+        unavailable,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    #[test]
+    fn test_block_stats() {
+        let ours = Pubkey::new_unique();
+        let theirs = Pubkey::new_unique();
+        let transaction = |payer: Pubkey, err: Option<serde_json::Value>| {
+            json!({
+                "transaction": {
+                    "signatures": [],
+                    "message": {
+                        "header": {
+                            "numRequiredSignatures": 1,
+                            "numReadonlySignedAccounts": 0,
+                            "numReadonlyUnsignedAccounts": 1,
+                        },
+                        "accountKeys": [payer.to_string()],
+                        "recentBlockhash": "11111111111111111111111111111111",
+                        "instructions": [],
+                    },
+                },
+                "meta": {
+                    "err": err,
+                    "status": {"Ok": null},
+                    "fee": 5000,
+                    "preBalances": [],
+                    "postBalances": [],
+                    "logMessages": [
+                        "Program ComputeBudget111111111111111111111111111111 invoke [1]",
+                        "Program ComputeBudget111111111111111111111111111111 success",
+                        "Program Nuke111111111111111111111111111111111111 invoke [1]",
+                        "Program 11111111111111111111111111111111 invoke [2]",
+                        "Program 11111111111111111111111111111111 success",
+                        "Program Nuke111111111111111111111111111111111111 consumed 1200 of \
+                         200000 compute units",
+                        "Program Nuke111111111111111111111111111111111111 success",
+                    ],
+                },
+            })
+        };
+        let block: UiConfirmedBlock = serde_json::from_value(json!({
+            "previousBlockhash": "11111111111111111111111111111111",
+            "blockhash": "11111111111111111111111111111111",
+            "parentSlot": 9,
+            "transactions": [
+                transaction(ours, None),
+                transaction(ours, Some(json!({"InstructionError": [1, {"Custom": 1}]}))),
+                transaction(theirs, None),
+            ],
+            "rewards": [{
+                "pubkey": theirs.to_string(),
+                "lamports": 7500,
+                "postBalance": 1_000_000,
+                "rewardType": "Fee",
+            }],
+        }))
+        .unwrap();
+
+        let stats = block_stats(10, &block, &HashSet::from([ours]));
+        assert_eq!(
+            stats,
+            SlotStats {
+                slot: 10,
+                skipped: false,
+                transactions: 3,
+                our_transactions: 2,
+                failed: 1,
+                compute_units: 3600,
+                fees: 15000,
+                leader_fees: 7500,
+            }
+        );
+        assert!((stats.failure_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+
+        let skipped = SlotStats {
+            slot: 11,
+            skipped: true,
+            ..SlotStats::default()
+        };
+        let report = BlockStatsReport::new(10, 11, vec![stats, skipped], 0);
+        assert_eq!(report.skipped_slots, 1);
+        assert_eq!(report.our_transactions, 2);
+    }
+
+    #[test]
+    fn test_available_slots() {
+        assert_eq!(available_slots(100, 200, 0, 300), 100..=200);
+        // A run's end slot can be ahead of the confirmed slot, and old blocks get purged
+        assert_eq!(available_slots(100, 200, 120, 150), 120..=150);
+        assert!(available_slots(100, 200, 250, 300).is_empty());
+        assert!(available_slots(100, 200, 0, 50).is_empty());
+        assert_eq!(slot_count(&available_slots(100, 200, 120, 150)), 31);
+        assert_eq!(slot_count(&available_slots(100, 200, 0, 50)), 0);
+    }
+
+    #[test]
+    fn test_slot_chunks() {
+        let chunks: Vec<_> = slot_chunks(5..=2_500).collect();
+        assert_eq!(chunks, [5..=1_004, 1_005..=2_004, 2_005..=2_500]);
+        assert_eq!(slot_chunks(7..=7).collect::<Vec<_>>(), [7..=7]);
+        assert_eq!(slot_chunks(8..=7).count(), 0);
+    }
+}
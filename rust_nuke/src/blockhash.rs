@@ -1,16 +1,19 @@
 use {
     crate::bench_tps_client::*,
+    crossbeam_channel::{Receiver, RecvTimeoutError},
     log::{debug, error, info, trace, warn},
     rayon::prelude::*,
     solana_client::{
         connection_cache,
+        pubsub_client::{PubsubClient, PubsubSlotClientSubscription},
         rpc_client::RpcClient,
+        rpc_response::SlotInfo,
         // tpu_client::{TpuClient, TpuClientConfig},
     },
     solana_core::gen_keys::GenKeys,
     solana_measure::measure::Measure,
     solana_sdk::{
-        clock::{DEFAULT_MS_PER_SLOT, DEFAULT_S_PER_SLOT, MAX_PROCESSING_AGE},
+        clock::{Slot, DEFAULT_MS_PER_SLOT, DEFAULT_S_PER_SLOT, MAX_PROCESSING_AGE},
         commitment_config::CommitmentConfig,
         hash::Hash,
         instruction::{AccountMeta, Instruction},
@@ -30,7 +33,7 @@ use {
         process::exit,
         // process::exit,
         sync::{
-            atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering},
+            atomic::{AtomicBool, AtomicIsize, AtomicU64, AtomicUsize, Ordering},
             mpsc, Arc, Mutex, RwLock,
         },
         thread::{sleep, Builder, JoinHandle},
@@ -38,10 +41,18 @@ use {
     },
 };
 
+// How long the blockhash may go without changing before the service gives up on it
+const BLOCKHASH_STUCK_SECS: u64 = 120;
+
 pub fn get_latest_blockhash<T: BenchTpsClient>(client: &T) -> Hash {
+    get_latest_blockhash_and_height(client).0
+}
+
+/// Like `get_latest_blockhash`, along with the last block height the blockhash can land at
+pub fn get_latest_blockhash_and_height<T: BenchTpsClient>(client: &T) -> (Hash, u64) {
     loop {
         match client.get_latest_blockhash_with_commitment(CommitmentConfig::processed()) {
-            Ok((blockhash, _)) => return blockhash,
+            Ok(latest) => return latest,
             Err(err) => {
                 info!("Couldn't get last blockhash: {:?}", err);
                 sleep(Duration::from_secs(1));
@@ -54,11 +65,22 @@ pub fn get_new_latest_blockhash<T: BenchTpsClient>(
     client: &Arc<T>,
     blockhash: &Hash,
 ) -> Option<Hash> {
+    get_new_latest_blockhash_and_height(client, blockhash).map(|(blockhash, _)| blockhash)
+}
+
+/// Like `get_new_latest_blockhash`, along with the last block height the new blockhash can
+/// land at
+pub fn get_new_latest_blockhash_and_height<T: BenchTpsClient>(
+    client: &Arc<T>,
+    blockhash: &Hash,
+) -> Option<(Hash, u64)> {
     let start = Instant::now();
     while start.elapsed().as_secs() < 5 {
-        if let Ok(new_blockhash) = client.get_latest_blockhash() {
-            if new_blockhash != *blockhash {
-                return Some(new_blockhash);
+        if let Ok(latest) =
+            client.get_latest_blockhash_with_commitment(CommitmentConfig::processed())
+        {
+            if latest.0 != *blockhash {
+                return Some(latest);
             }
         }
         // println!("Got same blockhash ({:?}), will retry...", blockhash);
@@ -69,25 +91,28 @@ pub fn get_new_latest_blockhash<T: BenchTpsClient>(
     None
 }
 
+/// Keep `blockhash` and its last valid block height up to date until `exit_signal` is set. If
+/// the blockhash stops changing, sets `exit_signal` itself and fails with `BlockhashStuck`.
 pub fn poll_blockhash<T: BenchTpsClient>(
     exit_signal: &Arc<AtomicBool>,
-    blockhash: &Arc<RwLock<Hash>>,
+    blockhash: &Arc<RwLock<(Hash, u64)>>,
     client: &Arc<T>,
     // id: &Pubkey,
-) {
+) -> Result<()> {
     let mut blockhash_last_updated = Instant::now();
     let mut last_error_log = Instant::now();
     loop {
         let blockhash_updated = {
-            let old_blockhash = *blockhash.read().unwrap();
-            if let Some(new_blockhash) = get_new_latest_blockhash(client, &old_blockhash) {
-                *blockhash.write().unwrap() = new_blockhash;
+            let (old_blockhash, _) = *blockhash.read().unwrap();
+            if let Some(latest) = get_new_latest_blockhash_and_height(client, &old_blockhash) {
+                *blockhash.write().unwrap() = latest;
                 blockhash_last_updated = Instant::now();
                 true
             } else {
-                if blockhash_last_updated.elapsed().as_secs() > 120 {
-                    eprintln!("Blockhash is stuck");
-                    exit(1)
+                if blockhash_last_updated.elapsed().as_secs() > BLOCKHASH_STUCK_SECS {
+                    error!("Blockhash is stuck");
+                    exit_signal.store(true, Ordering::Relaxed);
+                    return Err(BenchTpsError::BlockhashStuck);
                 } else if blockhash_last_updated.elapsed().as_secs() > 30
                     && last_error_log.elapsed().as_secs() >= 1
                 {
@@ -99,9 +124,308 @@ pub fn poll_blockhash<T: BenchTpsClient>(
         };
 
         if exit_signal.load(Ordering::Relaxed) {
-            break;
+            return Ok(());
         }
 
         sleep(Duration::from_millis(50));
     }
 }
+
+/// Latest slot seen, as kept by `follow_slots`
+#[derive(Default)]
+pub struct SlotProgress {
+    slot: AtomicU64,
+}
+
+/// Refresh `blockhash` and its last valid block height whenever `slots` reports a new slot,
+/// instead of polling for them, until
+/// `exit_signal` is set. If the blockhash stops changing or the subscription closes, sets
+/// `exit_signal` itself and fails.
+pub fn follow_slots<T: BenchTpsClient>(
+    exit_signal: &AtomicBool,
+    blockhash: &RwLock<(Hash, u64)>,
+    progress: &SlotProgress,
+    slots: &Receiver<SlotInfo>,
+    client: &T,
+) -> Result<()> {
+    let mut blockhash_last_updated = Instant::now();
+    while !exit_signal.load(Ordering::Relaxed) {
+        match slots.recv_timeout(Duration::from_millis(DEFAULT_MS_PER_SLOT)) {
+            Ok(SlotInfo { slot, .. }) if slot > progress.slot.load(Ordering::Relaxed) => {
+                progress.slot.store(slot, Ordering::Relaxed);
+                match client.get_latest_blockhash_with_commitment(CommitmentConfig::processed()) {
+                    Ok(latest) => {
+                        if latest.0 != blockhash.read().unwrap().0 {
+                            *blockhash.write().unwrap() = latest;
+                            blockhash_last_updated = Instant::now();
+                        }
+                    }
+                    Err(err) => debug!("Couldn't get blockhash for slot {}: {:?}", slot, err),
+                }
+            }
+            // Notifications can repeat or arrive out of order, only a newer slot matters
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                error!("Slot subscription closed");
+                exit_signal.store(true, Ordering::Relaxed);
+                return Err(BenchTpsError::Custom(
+                    "slot subscription closed".to_string(),
+                ));
+            }
+        }
+        if blockhash_last_updated.elapsed().as_secs() > BLOCKHASH_STUCK_SECS {
+            error!("Blockhash is stuck");
+            exit_signal.store(true, Ordering::Relaxed);
+            return Err(BenchTpsError::BlockhashStuck);
+        }
+    }
+    Ok(())
+}
+
+/// Keeps a shared blockhash and its last valid block height fresh on a background thread,
+/// either by polling for them or by following slot notifications. Create one per run and hand
+/// it to everything that signs, rather than starting a poller per batch of work. The thread
+/// stops on `shutdown` or when the service is dropped.
+pub struct BlockhashService {
+    // Kept under one lock with its last valid block height, so the two are always read as a
+    // pair
+    blockhash: Arc<RwLock<(Hash, u64)>>,
+    // Only kept up to date when following slot notifications
+    progress: Option<Arc<SlotProgress>>,
+    exit_signal: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<()>>>,
+    subscription: Option<PubsubSlotClientSubscription>,
+}
+
+impl BlockhashService {
+    /// Fetch the latest blockhash and start polling for newer ones
+    pub fn new<T: 'static + BenchTpsClient + Send + Sync>(client: &Arc<T>) -> Self {
+        let blockhash = Arc::new(RwLock::new(get_latest_blockhash_and_height(
+            client.as_ref(),
+        )));
+        let exit_signal = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let exit_signal = exit_signal.clone();
+            let blockhash = blockhash.clone();
+            let client = client.clone();
+            Builder::new()
+                .name("solana-blockhash-poller".to_string())
+                .spawn(move || poll_blockhash(&exit_signal, &blockhash, &client))
+                .unwrap()
+        };
+        Self {
+            blockhash,
+            progress: None,
+            exit_signal,
+            thread: Some(thread),
+            subscription: None,
+        }
+    }
+
+    /// Fetch the latest blockhash and refresh it each time the pubsub endpoint at
+    /// `websocket_url` reports a new slot
+    pub fn subscribe<T: 'static + BenchTpsClient + Send + Sync>(
+        client: &Arc<T>,
+        websocket_url: &str,
+    ) -> Result<Self> {
+        let latest = client.get_latest_blockhash_with_commitment(CommitmentConfig::processed())?;
+        let (subscription, slots) = PubsubClient::slot_subscribe(websocket_url)
+            .map_err(|err| BenchTpsError::Custom(format!("slot subscription failed: {}", err)))?;
+        let blockhash = Arc::new(RwLock::new(latest));
+        let progress = Arc::new(SlotProgress::default());
+        let exit_signal = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let exit_signal = exit_signal.clone();
+            let blockhash = blockhash.clone();
+            let progress = progress.clone();
+            let client = client.clone();
+            Builder::new()
+                .name("solana-blockhash-slots".to_string())
+                .spawn(move || {
+                    follow_slots(&exit_signal, &blockhash, &progress, &slots, client.as_ref())
+                })
+                .unwrap()
+        };
+        Ok(Self {
+            blockhash,
+            progress: Some(progress),
+            exit_signal,
+            thread: Some(thread),
+            subscription: Some(subscription),
+        })
+    }
+
+    pub fn blockhash(&self) -> Hash {
+        self.blockhash.read().unwrap().0
+    }
+
+    /// Last block height the current blockhash can land at
+    pub fn last_valid_block_height(&self) -> u64 {
+        self.blockhash.read().unwrap().1
+    }
+
+    /// The current blockhash and the last block height it can land at, read together
+    pub fn latest_blockhash(&self) -> (Hash, u64) {
+        *self.blockhash.read().unwrap()
+    }
+
+    /// Latest slot reported by the subscription, if one has been yet
+    pub fn slot(&self) -> Option<Slot> {
+        self.progress
+            .as_ref()
+            .map(|progress| progress.slot.load(Ordering::Relaxed))
+            .filter(|slot| *slot > 0)
+    }
+
+    /// Whether the thread has stopped, either because it was shut down or because it gave up
+    /// on the blockhash
+    pub fn is_stopped(&self) -> bool {
+        self.exit_signal.load(Ordering::Relaxed)
+    }
+
+    /// Stop the thread and wait for it. Fails if it gave up on the blockhash before being
+    /// stopped.
+    pub fn shutdown(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.exit_signal.store(true, Ordering::Relaxed);
+        let result = match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(err)) => {
+                info!("  join() failed with: {:?}", err);
+                Ok(())
+            }
+            None => Ok(()),
+        };
+        // Shutting the subscription down waits for the next notification, which a websocket
+        // that has gone quiet never sends. The slots thread has already let go of the receiver,
+        // so leave the unsubscribing to a thread of its own.
+        if let Some(mut subscription) = self.subscription.take() {
+            let unsubscribe = Builder::new()
+                .name("solana-blockhash-unsubscribe".to_string())
+                .spawn(move || {
+                    if let Err(err) = subscription.shutdown() {
+                        debug!("Couldn't shut the slot subscription down: {:?}", err);
+                    }
+                });
+            if let Err(err) = unsubscribe {
+                debug!("Couldn't spawn the unsubscribe thread: {}", err);
+            }
+        }
+        result
+    }
+}
+
+impl Drop for BlockhashService {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            warn!("Blockhash service stopped early: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::bench_tps_client::mock_client::MockClient,
+        serde_json::json,
+        std::{net::TcpListener, thread::spawn},
+        tungstenite::Message as WsMessage,
+    };
+
+    /// Accept one slot subscription and report `slots` new slots every few milliseconds, each
+    /// twice, until the subscriber goes away. Then go quiet, leaving the websocket open.
+    fn pubsub_stand_in(first_slot: Slot, slots: u64) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let request: serde_json::Value =
+                serde_json::from_str(socket.read_message().unwrap().to_text().unwrap()).unwrap();
+            assert_eq!(request["method"], "slotSubscribe");
+            let response = json!({"jsonrpc": "2.0", "result": 7, "id": request["id"]});
+            socket
+                .write_message(WsMessage::Text(response.to_string()))
+                .unwrap();
+            for slot in first_slot..first_slot + slots {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "slotNotification",
+                    "params": {
+                        "result": {"parent": slot - 1, "root": slot - 1, "slot": slot},
+                        "subscription": 7,
+                    },
+                });
+                for _ in 0..2 {
+                    if socket
+                        .write_message(WsMessage::Text(notification.to_string()))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                sleep(Duration::from_millis(20));
+            }
+            while socket.read_message().is_ok() {}
+        });
+        url
+    }
+
+    #[test]
+    fn test_blockhash_service_polls() {
+        let client = Arc::new(MockClient::default());
+        let service = BlockhashService::new(&client);
+        let (first_blockhash, first_height) = service.latest_blockhash();
+        assert!(first_height > 0);
+
+        let start = Instant::now();
+        while service.blockhash() == first_blockhash {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "blockhash never refreshed"
+            );
+            sleep(Duration::from_millis(10));
+        }
+        // Every new blockhash from the mock can land later than the one before it
+        assert!(service.last_valid_block_height() > first_height);
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_blockhash_service_follows_slots() {
+        let client = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+        let url = pubsub_stand_in(100, 1000);
+        let service = BlockhashService::subscribe(&client, &url).unwrap();
+
+        let start = Instant::now();
+        while service.slot().map_or(true, |slot| slot < 103) {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "no slots received"
+            );
+            sleep(Duration::from_millis(10));
+        }
+        assert_ne!(service.blockhash(), Hash::default());
+        assert!(service.last_valid_block_height() > 0);
+        assert!(!service.is_stopped());
+        service.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_blockhash_service_shuts_down_on_quiet_websocket() {
+        let client = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+        let url = pubsub_stand_in(100, 2);
+        let service = BlockhashService::subscribe(&client, &url).unwrap();
+        while service.slot().map_or(true, |slot| slot < 101) {
+            sleep(Duration::from_millis(10));
+        }
+
+        let start = Instant::now();
+        service.shutdown().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
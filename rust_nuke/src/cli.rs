@@ -0,0 +1,864 @@
+use {
+    crate::{
+        priority_fee::{ComputeBudget, ComputeUnitPrice},
+        rate_limit::{is_valid_rate, RateProfile},
+        report::{report_slot_range, ReportFormat},
+        workload::Workload,
+    },
+    clap::{crate_description, crate_name, App, AppSettings, Arg, ArgMatches, SubCommand},
+    solana_clap_utils::input_validators::{
+        is_host_port, is_keypair, is_parsable, is_pubkey, is_url_or_moniker,
+    },
+    solana_cli_config::{ConfigInput, CONFIG_FILE},
+    solana_client::tpu_client::DEFAULT_FANOUT_SLOTS,
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        signature::{read_keypair_file, Keypair},
+    },
+    std::{
+        net::SocketAddr,
+        path::{Path, PathBuf},
+        process::exit,
+        time::Duration,
+    },
+};
+
+const NUM_LAMPORTS_PER_ACCOUNT_DEFAULT: u64 = 200_000;
+const KEYPAIR_COUNT_DEFAULT: usize = 6;
+
+/// The action to perform for a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Derive the keypairs from the funding keypair and fund them
+    Fund,
+    /// Send the balances of the derived keypairs back to the funding keypair
+    Defund,
+    /// Fund the derived keypairs, spam the cluster with them, then defund
+    Spam,
+    /// Fund the derived keypairs and write transactions signed by them to a corpus file
+    Generate,
+    /// Send the transactions of a corpus file
+    Replay,
+    /// Print the balances of the funding keypair and the derived keypairs
+    Balances,
+    /// Derive the keypairs and write them to a keypair store without funding them
+    SaveKeypairs,
+    /// Print the manifest and keypairs of a keypair store
+    ListKeypairs,
+    /// Report what the blocks of a range of slots held
+    Stats,
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self::Spam
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalClientType {
+    // Submits transactions to an Rpc node using an RpcClient
+    RpcClient,
+    // Submits transactions directly to leaders using a TpuClient, broadcasting to upcoming leaders
+    // via TpuClient default configuration
+    TpuClient,
+    // Submits transactions directly to a single validator's TPU using a ThinClient, without any
+    // leader schedule routing
+    ThinClient,
+}
+
+impl Default for ExternalClientType {
+    fn default() -> Self {
+        Self::RpcClient
+    }
+}
+
+/// Holds the configuration for a single run of the tool
+pub struct Config {
+    pub command: Command,
+    pub json_rpc_url: String,
+    pub websocket_url: String,
+    pub id: Keypair,
+    pub keypair_count: usize,
+    pub num_lamports_per_account: u64,
+    pub commitment_config: CommitmentConfig,
+    pub external_client_type: ExternalClientType,
+    pub use_quic: bool,
+    pub tpu_fanout_slots: u64,
+    pub rpc_tpu_sockets: Option<(SocketAddr, SocketAddr)>,
+    pub threads: usize,
+    pub duration: Duration,
+    pub tx_limit: Option<usize>,
+    pub sustained: bool,
+    pub thread_batch_sleep_ms: usize,
+    pub sample_period: u64,
+    pub report_path: Option<PathBuf>,
+    pub report_format: ReportFormat,
+    pub workload: Workload,
+    pub compute_budget: ComputeBudget,
+    pub use_durable_nonce: bool,
+    /// Refresh the blockhash on slot notifications from `websocket_url` instead of polling
+    pub blockhash_subscription: bool,
+    /// Follow which leaders skip slots or produce small blocks while the run goes on
+    pub leader_report: bool,
+    /// Corpus file written by `generate` and read by `replay`
+    pub corpus_path: Option<PathBuf>,
+    /// Mixed into the keypair derivation so runs from one funding keypair don't collide
+    pub keypair_namespace: Option<String>,
+    /// Directory keypairs are loaded from, or saved to if it doesn't hold a store yet
+    pub keypair_store: Option<PathBuf>,
+    /// Target send rate over the run, unlimited if `None`
+    pub rate_profile: Option<RateProfile>,
+    pub replay_batch_size: usize,
+    /// First and last slot the stats command covers
+    pub slot_range: Option<(u64, u64)>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            command: Command::default(),
+            json_rpc_url: ConfigInput::default().json_rpc_url,
+            websocket_url: ConfigInput::default().websocket_url,
+            id: Keypair::new(),
+            keypair_count: KEYPAIR_COUNT_DEFAULT,
+            num_lamports_per_account: NUM_LAMPORTS_PER_ACCOUNT_DEFAULT,
+            commitment_config: CommitmentConfig::confirmed(),
+            external_client_type: ExternalClientType::default(),
+            use_quic: false,
+            tpu_fanout_slots: DEFAULT_FANOUT_SLOTS,
+            rpc_tpu_sockets: None,
+            threads: 4,
+            duration: Duration::new(std::u64::MAX, 0),
+            tx_limit: None,
+            sustained: false,
+            thread_batch_sleep_ms: 1000,
+            sample_period: 1,
+            report_path: None,
+            report_format: ReportFormat::Json,
+            workload: Workload::default(),
+            compute_budget: ComputeBudget::default(),
+            use_durable_nonce: false,
+            blockhash_subscription: false,
+            leader_report: false,
+            corpus_path: None,
+            keypair_namespace: None,
+            keypair_store: None,
+            rate_profile: None,
+            replay_batch_size: 64,
+            slot_range: None,
+        }
+    }
+}
+
+/// Defines and builds the CLI args for a run of the tool
+pub fn build_args<'a, 'b>(version: &'b str) -> App<'a, 'b> {
+    App::new(crate_name!())
+        .about(crate_description!())
+        .version(version)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg({
+            let arg = Arg::with_name("config_file")
+                .short("C")
+                .long("config")
+                .value_name("FILEPATH")
+                .takes_value(true)
+                .global(true)
+                .help("Configuration file to use");
+            if let Some(ref config_file) = *CONFIG_FILE {
+                arg.default_value(config_file)
+            } else {
+                arg
+            }
+        })
+        .arg(
+            Arg::with_name("json_rpc_url")
+                .short("u")
+                .long("url")
+                .value_name("URL_OR_MONIKER")
+                .takes_value(true)
+                .global(true)
+                .validator(is_url_or_moniker)
+                .help(
+                    "URL for Solana's JSON RPC or moniker (or their first letter): \
+                     [mainnet-beta, testnet, devnet, localhost]",
+                ),
+        )
+        .arg(
+            Arg::with_name("websocket_url")
+                .long("ws")
+                .value_name("URL")
+                .takes_value(true)
+                .global(true)
+                .validator(is_url_or_moniker)
+                .help("WebSocket URL for the solana cluster"),
+        )
+        .arg(
+            Arg::with_name("identity")
+                .short("k")
+                .long("keypair")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .validator(is_keypair)
+                .help("File containing the funding keypair"),
+        )
+        .arg(
+            Arg::with_name("keypair_count")
+                .short("n")
+                .long("keypair-count")
+                .value_name("NUM")
+                .takes_value(true)
+                .global(true)
+                .help("Number of keypairs to derive from the funding keypair. Default: 6"),
+        )
+        .arg(
+            Arg::with_name("num_lamports_per_account")
+                .long("lamports-per-account")
+                .value_name("LAMPORTS")
+                .takes_value(true)
+                .global(true)
+                .help(
+                    "Number of lamports per account, on top of rent exemption. \
+                     Default: 200000",
+                ),
+        )
+        .arg(
+            Arg::with_name("commitment")
+                .long("commitment")
+                .value_name("COMMITMENT_LEVEL")
+                .takes_value(true)
+                .global(true)
+                .possible_values(&["processed", "confirmed", "finalized"])
+                .hide_possible_values(true)
+                .help(
+                    "Commitment level for RPC requests [possible values: \
+                     processed, confirmed, finalized]",
+                ),
+        )
+        .arg(
+            Arg::with_name("rpc_client")
+                .long("use-rpc-client")
+                .global(true)
+                .conflicts_with_all(&["tpu_client", "thin_client"])
+                .help("Submit transactions with a RpcClient"),
+        )
+        .arg(
+            Arg::with_name("tpu_client")
+                .long("use-tpu-client")
+                .global(true)
+                .conflicts_with_all(&["rpc_client", "thin_client"])
+                .help("Submit transactions straight to the upcoming leaders with a TpuClient"),
+        )
+        .arg(
+            Arg::with_name("thin_client")
+                .long("use-thin-client")
+                .global(true)
+                .conflicts_with_all(&["rpc_client", "tpu_client"])
+                .help(
+                    "Submit transactions to a single validator with a ThinClient; \
+                     requires --rpc-addr and --tpu-addr",
+                ),
+        )
+        .arg(
+            Arg::with_name("rpc_addr")
+                .long("rpc-addr")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .global(true)
+                .validator(is_host_port)
+                .help("Validator RPC address to use with the ThinClient"),
+        )
+        .arg(
+            Arg::with_name("tpu_addr")
+                .long("tpu-addr")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .global(true)
+                .validator(is_host_port)
+                .help("Validator TPU address to use with the ThinClient"),
+        )
+        .arg(
+            Arg::with_name("tpu_use_quic")
+                .long("tpu-use-quic")
+                .global(true)
+                .help(
+                    "Submit transactions via QUIC; only affects TpuClient and \
+                     ThinClient sends",
+                ),
+        )
+        .arg(
+            Arg::with_name("tpu_fanout_slots")
+                .long("tpu-fanout-slots")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .global(true)
+                .help("Number of upcoming leader slots the TpuClient sends each transaction to"),
+        )
+        .arg(
+            Arg::with_name("report")
+                .long("report")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .help(
+                    "Write a machine-readable report of the run, or the block stats of the \
+                     stats command, to this path",
+                ),
+        )
+        .arg(
+            Arg::with_name("report_format")
+                .long("report-format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .global(true)
+                .possible_values(&["json", "csv"])
+                .help(
+                    "Format of the report. CSV reports append one row per run. \
+                     Default: inferred from the report path, JSON otherwise",
+                ),
+        )
+        .arg(
+            Arg::with_name("compute_unit_limit")
+                .long("compute-unit-limit")
+                .value_name("UNITS")
+                .takes_value(true)
+                .global(true)
+                .validator(is_parsable::<u32>)
+                .help("Request this many compute units for every transaction sent"),
+        )
+        .arg(
+            Arg::with_name("compute_unit_price")
+                .long("compute-unit-price")
+                .value_name("MICROLAMPORTS")
+                .takes_value(true)
+                .global(true)
+                .validator(is_parsable::<u64>)
+                .help("Priority fee of every transaction sent, in micro-lamports per compute unit"),
+        )
+        .arg(
+            Arg::with_name("durable_nonce")
+                .long("durable-nonce")
+                .global(true)
+                .help(
+                    "Give every funded keypair a nonce account and sign funding and spam \
+                     transactions with it instead of a recent blockhash",
+                ),
+        )
+        .arg(
+            Arg::with_name("blockhash_subscription")
+                .long("blockhash-subscription")
+                .global(true)
+                .help(
+                    "Refresh the blockhash of a run when the websocket endpoint reports a new \
+                     slot, instead of polling the RPC endpoint for it",
+                ),
+        )
+        .arg(
+            Arg::with_name("leader_report")
+                .long("leader-report")
+                .global(true)
+                .help(
+                    "Compare the leader schedule against the blocks produced during spam and \
+                     replay runs, and print how many slots each leader skipped",
+                ),
+        )
+        .arg(
+            Arg::with_name("keypair_namespace")
+                .long("namespace")
+                .value_name("NAME")
+                .takes_value(true)
+                .global(true)
+                .help(
+                    "Derive a separate set of keypairs for this namespace, so concurrent runs \
+                     from the same funding keypair don't use each other's accounts. The same \
+                     namespace always derives the same keypairs",
+                ),
+        )
+        .arg(
+            Arg::with_name("keypair_store")
+                .long("keypair-store")
+                .value_name("DIR")
+                .takes_value(true)
+                .global(true)
+                .help(
+                    "Load the keypairs from this directory instead of deriving them, \
+                     saving them there first if it doesn't hold any yet",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fund")
+                .about("Derive keypairs from the funding keypair and fund them"),
+        )
+        .subcommand(
+            SubCommand::with_name("defund")
+                .about("Return the balances of the derived keypairs to the funding keypair"),
+        )
+        .subcommand(
+            SubCommand::with_name("spam")
+                .about("Fund the derived keypairs, spam the cluster, then defund them")
+                .arg(
+                    Arg::with_name("duration")
+                        .long("duration")
+                        .value_name("SECS")
+                        .takes_value(true)
+                        .help("Seconds to run the benchmark, defaults to forever"),
+                )
+                .arg(
+                    Arg::with_name("tx_limit")
+                        .long("tx-limit")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .help("Stop once this many transactions have been generated"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .long("threads")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .help("Number of sender threads"),
+                )
+                .arg(Arg::with_name("sustained").long("sustained").help(
+                    "Use sustained performance mode vs. peak mode. \
+                     This overlaps the tx generation with transfers.",
+                ))
+                .arg(
+                    Arg::with_name("thread_batch_sleep_ms")
+                        .long("thread-batch-sleep-ms")
+                        .value_name("MS")
+                        .takes_value(true)
+                        .help("Per-thread-per-iteration sleep in ms"),
+                )
+                .arg(
+                    Arg::with_name("sample_period")
+                        .long("sample-period")
+                        .value_name("SECS")
+                        .takes_value(true)
+                        .help("Seconds between cluster transaction count samples. Default: 1"),
+                )
+                .args(&transaction_args())
+                .args(&rate_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("generate")
+                .about(
+                    "Fund the derived keypairs and write signed transactions to a corpus file \
+                     for a later replay",
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Corpus file to write"),
+                )
+                .arg(
+                    Arg::with_name("tx_limit")
+                        .long("tx-limit")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Number of transactions to generate"),
+                )
+                .args(&transaction_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Send the transactions of a corpus file written by generate")
+                .arg(
+                    Arg::with_name("input")
+                        .long("input")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Corpus file to replay"),
+                )
+                .args(&rate_args())
+                .arg(
+                    Arg::with_name("replay_batch_size")
+                        .long("batch-size")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .validator(is_parsable::<usize>)
+                        .help("Transactions handed to each send_batch call. Default: 64"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("balances")
+                .about("Show the balances of the funding keypair and derived keypairs"),
+        )
+        .subcommand(
+            SubCommand::with_name("save-keypairs")
+                .about("Derive keypairs from the funding keypair and save them to --keypair-store"),
+        )
+        .subcommand(
+            SubCommand::with_name("list-keypairs")
+                .about("Show the manifest and keypairs of --keypair-store"),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about(
+                    "Report per-slot transaction counts, our keypairs' share, failures, compute \
+                     units, skipped slots and fees over a range of slots to --report",
+                )
+                .arg(
+                    Arg::with_name("start_slot")
+                        .long("start-slot")
+                        .value_name("SLOT")
+                        .takes_value(true)
+                        .requires("end_slot")
+                        .required_unless("run_report")
+                        .validator(is_parsable::<u64>)
+                        .help("First slot to report on"),
+                )
+                .arg(
+                    Arg::with_name("end_slot")
+                        .long("end-slot")
+                        .value_name("SLOT")
+                        .takes_value(true)
+                        .requires("start_slot")
+                        .validator(is_parsable::<u64>)
+                        .help("Last slot to report on"),
+                )
+                .arg(
+                    Arg::with_name("run_report")
+                        .long("run-report")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .conflicts_with("start_slot")
+                        .help("Report on the slots of the run that wrote this JSON report"),
+                ),
+        )
+}
+
+/// Args that limit the send rate, shared by `spam` and `replay`
+fn rate_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("target_tps")
+            .long("target-tps")
+            .value_name("TPS")
+            .takes_value(true)
+            .conflicts_with("rate_profile")
+            .validator(|tps| match tps.parse() {
+                Ok(tps) if is_valid_rate(tps) => Ok(()),
+                _ => Err(format!("expected a positive rate, got {:?}", tps)),
+            })
+            .help("Send no faster than this many transactions per second"),
+        Arg::with_name("rate_profile")
+            .long("rate-profile")
+            .value_name("PROFILE")
+            .takes_value(true)
+            .validator(|profile| profile.parse::<RateProfile>().map(|_| ()))
+            .help(
+                "How the target send rate changes over the run: constant:TPS, \
+                 linear:START:END:SECS (ramp), step:START:STEP:SECS (add STEP tps every SECS) \
+                 or spike:BASE:PEAK:AT_SECS:SECS (burst to PEAK for SECS)",
+            ),
+    ]
+}
+
+/// Args that shape the generated transactions, shared by `spam` and `generate`
+fn transaction_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("workload")
+            .long("workload")
+            .value_name("KIND=WEIGHT,...")
+            .takes_value(true)
+            .validator(|spec| Workload::parse_weights(&spec).map(|_| ()))
+            .help(
+                "Mix of transactions to send, e.g. transfer=70,spam=20,memo=10. \
+                 Kinds are transfer, spam (nuke program) and memo. \
+                 Default: transfer=1",
+            ),
+        Arg::with_name("transfer_amount")
+            .long("transfer-amount")
+            .value_name("LAMPORTS")
+            .takes_value(true)
+            .help("Lamports moved by each transfer and spam instruction. Default: 1"),
+        Arg::with_name("nuke_program_id")
+            .long("nuke-program-id")
+            .value_name("PUBKEY")
+            .takes_value(true)
+            .validator(is_pubkey)
+            .help("Address of the deployed nuke program"),
+        Arg::with_name("loop_counter")
+            .long("loop-counter")
+            .value_name("NUM or MIN-MAX")
+            .takes_value(true)
+            .help(
+                "Number of PDAs each spam instruction derives, \
+                 which sets how compute heavy it is. \
+                 A range picks uniformly per transaction. Default: 85",
+            ),
+        Arg::with_name("compute_unit_price_sweep")
+            .long("compute-unit-price-sweep")
+            .value_name("START-END[:STEPS]")
+            .takes_value(true)
+            .validator(|spec| match spec.parse::<ComputeUnitPrice>() {
+                Ok(price) if price.is_sweep() => Ok(()),
+                Ok(_) => Err("expected START-END[:STEPS]".to_string()),
+                Err(err) => Err(err),
+            })
+            .help(
+                "Step the compute unit price from START to END micro-lamports \
+                 in STEPS evenly spaced tiers over the run, and report the \
+                 landing rate of each tier. Requires --duration or --tx-limit. \
+                 Default steps: 10",
+            ),
+    ]
+}
+
+/// Parses a clap `ArgMatches` structure into a `Config`
+pub fn extract_args(matches: &ArgMatches) -> Config {
+    let mut args = Config::default();
+
+    let (command, sub_matches) = match matches.subcommand() {
+        ("fund", Some(sub_matches)) => (Command::Fund, sub_matches),
+        ("defund", Some(sub_matches)) => (Command::Defund, sub_matches),
+        ("spam", Some(sub_matches)) => (Command::Spam, sub_matches),
+        ("generate", Some(sub_matches)) => (Command::Generate, sub_matches),
+        ("replay", Some(sub_matches)) => (Command::Replay, sub_matches),
+        ("balances", Some(sub_matches)) => (Command::Balances, sub_matches),
+        ("save-keypairs", Some(sub_matches)) => (Command::SaveKeypairs, sub_matches),
+        ("list-keypairs", Some(sub_matches)) => (Command::ListKeypairs, sub_matches),
+        ("stats", Some(sub_matches)) => (Command::Stats, sub_matches),
+        _ => {
+            eprintln!("{}", matches.usage());
+            exit(1);
+        }
+    };
+    args.command = command;
+
+    // Global args are propagated down to the subcommand, so read everything from there
+    let matches = sub_matches;
+
+    let config = if let Some(config_file) = matches.value_of("config_file") {
+        solana_cli_config::Config::load(config_file).unwrap_or_default()
+    } else {
+        solana_cli_config::Config::default()
+    };
+    let (_, json_rpc_url) = ConfigInput::compute_json_rpc_url_setting(
+        matches.value_of("json_rpc_url").unwrap_or(""),
+        &config.json_rpc_url,
+    );
+    args.json_rpc_url = json_rpc_url;
+
+    let (_, websocket_url) = ConfigInput::compute_websocket_url_setting(
+        matches.value_of("websocket_url").unwrap_or(""),
+        &config.websocket_url,
+        matches.value_of("json_rpc_url").unwrap_or(""),
+        &config.json_rpc_url,
+    );
+    args.websocket_url = websocket_url;
+
+    let (_, id_path) = ConfigInput::compute_keypair_path_setting(
+        matches.value_of("identity").unwrap_or(""),
+        &config.keypair_path,
+    );
+    match read_keypair_file(&id_path) {
+        Ok(id) => args.id = id,
+        Err(err) => {
+            eprintln!("could not read funding keypair {}: {}", id_path, err);
+            exit(1);
+        }
+    }
+
+    let (_, commitment_config) = ConfigInput::compute_commitment_config(
+        matches.value_of("commitment").unwrap_or(""),
+        &config.commitment,
+    );
+    args.commitment_config = commitment_config;
+
+    if matches.is_present("tpu_client") {
+        args.external_client_type = ExternalClientType::TpuClient;
+    } else if matches.is_present("thin_client") {
+        args.external_client_type = ExternalClientType::ThinClient;
+    } else if matches.is_present("rpc_client") {
+        args.external_client_type = ExternalClientType::RpcClient;
+    }
+
+    if matches.is_present("tpu_use_quic") {
+        args.use_quic = true;
+    }
+
+    if let Some(slots) = matches.value_of("tpu_fanout_slots") {
+        args.tpu_fanout_slots = slots.parse().expect("can't parse tpu-fanout-slots");
+    }
+
+    if let (Some(rpc_addr), Some(tpu_addr)) =
+        (matches.value_of("rpc_addr"), matches.value_of("tpu_addr"))
+    {
+        let rpc_addr = solana_net_utils::parse_host_port(rpc_addr).unwrap_or_else(|e| {
+            eprintln!("failed to parse rpc address: {}", e);
+            exit(1)
+        });
+        let tpu_addr = solana_net_utils::parse_host_port(tpu_addr).unwrap_or_else(|e| {
+            eprintln!("failed to parse tpu address: {}", e);
+            exit(1)
+        });
+        args.rpc_tpu_sockets = Some((rpc_addr, tpu_addr));
+    } else if args.external_client_type == ExternalClientType::ThinClient {
+        eprintln!("--use-thin-client requires both --rpc-addr and --tpu-addr");
+        exit(1);
+    }
+
+    if let Some(n) = matches.value_of("keypair_count") {
+        args.keypair_count = n.parse().expect("can't parse keypair-count");
+        // Transfers go from one half of the keypairs to the other
+        if args.keypair_count < 2 {
+            eprintln!("--keypair-count must be at least 2");
+            exit(1);
+        }
+    }
+
+    if let Some(v) = matches.value_of("num_lamports_per_account") {
+        args.num_lamports_per_account = v.to_string().parse().expect("can't parse lamports");
+    }
+
+    if let Some(t) = matches.value_of("threads") {
+        args.threads = t.to_string().parse().expect("can't parse threads");
+    }
+
+    if let Some(duration) = matches.value_of("duration") {
+        args.duration = Duration::new(
+            duration.to_string().parse().expect("can't parse duration"),
+            0,
+        );
+    }
+
+    if let Some(limit) = matches.value_of("tx_limit") {
+        args.tx_limit = Some(limit.to_string().parse().expect("can't parse tx-limit"));
+    }
+
+    args.sustained = matches.is_present("sustained");
+
+    if let Some(t) = matches.value_of("thread_batch_sleep_ms") {
+        args.thread_batch_sleep_ms = t
+            .to_string()
+            .parse()
+            .expect("can't parse thread-batch-sleep-ms");
+    }
+
+    if let Some(path) = matches.value_of("report") {
+        let path = PathBuf::from(path);
+        args.report_format = ReportFormat::from_path(&path);
+        args.report_path = Some(path);
+    }
+    match matches.value_of("report_format") {
+        Some("json") => args.report_format = ReportFormat::Json,
+        Some("csv") => args.report_format = ReportFormat::Csv,
+        _ => {}
+    }
+
+    if let Some(spec) = matches.value_of("workload") {
+        // Already checked by the validator
+        let weights = Workload::parse_weights(spec).unwrap();
+        args.workload = Workload::new(weights).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+    }
+    if let Some(amount) = matches.value_of("transfer_amount") {
+        args.workload.transfer_amount = amount.parse().expect("can't parse transfer-amount");
+    }
+    if let Some(program_id) = matches.value_of("nuke_program_id") {
+        args.workload.nuke_program_id = program_id.parse().expect("can't parse nuke-program-id");
+    }
+    if let Some(loop_counter) = matches.value_of("loop_counter") {
+        let (min, max) = loop_counter
+            .split_once('-')
+            .unwrap_or((loop_counter, loop_counter));
+        let min: u16 = min.parse().expect("can't parse loop-counter");
+        let max: u16 = max.parse().expect("can't parse loop-counter");
+        if min > max {
+            eprintln!("loop-counter range {} is empty", loop_counter);
+            exit(1);
+        }
+        args.workload.loop_counter = min..=max;
+    }
+
+    if let Some(period) = matches.value_of("sample_period") {
+        args.sample_period = period.parse().expect("can't parse sample-period");
+    }
+
+    if let Some(limit) = matches.value_of("compute_unit_limit") {
+        args.compute_budget.compute_unit_limit =
+            Some(limit.parse().expect("can't parse compute-unit-limit"));
+    }
+    if let Some(price) = matches.value_of("compute_unit_price") {
+        args.compute_budget.compute_unit_price = Some(ComputeUnitPrice::Fixed(
+            price.parse().expect("can't parse compute-unit-price"),
+        ));
+    }
+    args.use_durable_nonce = matches.is_present("durable_nonce");
+    args.blockhash_subscription = matches.is_present("blockhash_subscription");
+    args.leader_report = matches.is_present("leader_report");
+
+    if let Some(path) = matches
+        .value_of("output")
+        .or_else(|| matches.value_of("input"))
+    {
+        args.corpus_path = Some(PathBuf::from(path));
+    }
+    args.keypair_namespace = matches.value_of("keypair_namespace").map(str::to_string);
+    args.keypair_store = matches.value_of("keypair_store").map(PathBuf::from);
+    if args.keypair_store.is_none()
+        && matches!(args.command, Command::SaveKeypairs | Command::ListKeypairs)
+    {
+        eprintln!("save-keypairs and list-keypairs require --keypair-store");
+        exit(1);
+    }
+
+    if let Some(tps) = matches.value_of("target_tps") {
+        args.rate_profile = Some(RateProfile::Constant {
+            tps: tps.parse().expect("can't parse target-tps"),
+        });
+    }
+    if let Some(profile) = matches.value_of("rate_profile") {
+        // Already checked by the validator
+        args.rate_profile = Some(profile.parse().unwrap());
+    }
+    if let Some(size) = matches.value_of("replay_batch_size") {
+        args.replay_batch_size = size.parse().expect("can't parse batch-size");
+    }
+
+    if let Some(path) = matches.value_of("run_report") {
+        match report_slot_range(Path::new(path)) {
+            Ok(slot_range) => args.slot_range = Some(slot_range),
+            Err(err) => {
+                eprintln!("could not read the slots of run report {}: {}", path, err);
+                exit(1);
+            }
+        }
+    } else if let (Some(start_slot), Some(end_slot)) =
+        (matches.value_of("start_slot"), matches.value_of("end_slot"))
+    {
+        // Already checked by the validators
+        args.slot_range = Some((start_slot.parse().unwrap(), end_slot.parse().unwrap()));
+    }
+    if args.command == Command::Stats && args.report_path.is_none() {
+        eprintln!("stats requires --report");
+        exit(1);
+    }
+    if matches!(args.slot_range, Some((start_slot, end_slot)) if start_slot > end_slot) {
+        eprintln!("the first slot of the range comes after the last");
+        exit(1);
+    }
+
+    if let Some(sweep) = matches.value_of("compute_unit_price_sweep") {
+        if args.compute_budget.compute_unit_price.is_some() {
+            eprintln!("--compute-unit-price and --compute-unit-price-sweep are exclusive");
+            exit(1);
+        }
+        // A sweep is spread over the run, so the run needs an end
+        if matches.value_of("duration").is_none() && args.tx_limit.is_none() {
+            eprintln!("--compute-unit-price-sweep requires --duration or --tx-limit");
+            exit(1);
+        }
+        // Already checked by the validator
+        args.compute_budget.compute_unit_price = Some(sweep.parse().unwrap());
+    }
+
+    args
+}
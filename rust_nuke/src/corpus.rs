@@ -0,0 +1,414 @@
+use {
+    crate::{
+        bench::{generate_workload_txs, BenchTpsStats},
+        bench_tps_client::*,
+        cli::Config,
+        latency::{create_confirmation_thread, SignatureTracker},
+        nonce::{get_durable_nonces, nonce_accounts},
+        priority_fee::compute_unit_price_of,
+        rate_limit::RateLimiter,
+    },
+    log::*,
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
+    solana_sdk::{
+        clock::{Slot, MAX_PROCESSING_AGE},
+        commitment_config::CommitmentConfig,
+        hash::Hash,
+        signature::Keypair,
+        timing::duration_as_s,
+        transaction::Transaction,
+    },
+    std::{
+        collections::{HashMap, VecDeque},
+        error,
+        fs::File,
+        io::{self, BufReader, BufWriter, Read, Write},
+        path::Path,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Instant,
+    },
+};
+
+const CORPUS_MAGIC: &[u8; 8] = b"nukecrps";
+const CORPUS_VERSION: u32 = 1;
+
+// Transactions are at most a packet, so anything much bigger means the file is corrupt
+const MAX_RECORD_LEN: usize = 64 * 1024;
+
+/// What the transactions in a corpus were signed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignedWith {
+    /// One recent blockhash for the whole corpus, which expires `MAX_PROCESSING_AGE` slots after
+    /// the corpus was created
+    Blockhash(Hash),
+    /// Each sender's durable nonce. These never expire, but each sender only signs one
+    /// transaction since a nonce can only be used once.
+    DurableNonce,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorpusHeader {
+    pub version: u32,
+    pub signed_with: SignedWith,
+    pub creation_slot: Slot,
+    pub transaction_count: u64,
+}
+
+/// Writes a corpus: a magic number, then the header and each transaction as bincode records
+/// prefixed with their little-endian u32 length
+pub struct CorpusWriter {
+    writer: BufWriter<File>,
+}
+
+impl CorpusWriter {
+    pub fn create(path: &Path, header: &CorpusHeader) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(CORPUS_MAGIC)?;
+        write_record(&mut writer, header)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, transaction: &Transaction) -> io::Result<()> {
+        write_record(&mut self.writer, transaction)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Streams the transactions of a corpus written by `CorpusWriter`
+pub struct CorpusReader {
+    reader: BufReader<File>,
+    header: CorpusHeader,
+}
+
+impl CorpusReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CORPUS_MAGIC {
+            return Err(invalid_data("not a transaction corpus"));
+        }
+        let header: CorpusHeader =
+            read_record(&mut reader)?.ok_or_else(|| invalid_data("missing corpus header"))?;
+        if header.version != CORPUS_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported corpus version {}",
+                header.version
+            )));
+        }
+        Ok(Self { reader, header })
+    }
+
+    pub fn header(&self) -> &CorpusHeader {
+        &self.header
+    }
+
+    /// Read up to `max` transactions; fewer means the end of the corpus was reached
+    pub fn read_batch(&mut self, max: usize) -> io::Result<Vec<Transaction>> {
+        let mut batch = Vec::with_capacity(max);
+        while batch.len() < max {
+            match read_record(&mut self.reader)? {
+                Some(transaction) => batch.push(transaction),
+                None => break,
+            }
+        }
+        Ok(batch)
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn write_record<W: Write, T: Serialize>(writer: &mut W, value: &T) -> io::Result<()> {
+    let bytes = bincode::serialize(value).map_err(invalid_data)?;
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)
+}
+
+/// Read one record, `None` at the end of the file
+fn read_record<R: Read, T: DeserializeOwned>(reader: &mut R) -> io::Result<Option<T>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_RECORD_LEN {
+        return Err(invalid_data(format!("corpus record of {} bytes", len)));
+    }
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes)?;
+    bincode::deserialize(&bytes).map(Some).map_err(invalid_data)
+}
+
+/// Sign `config.tx_limit` transactions between the halves of `keypairs`, the same way a spam
+/// run would, and write them to a corpus at `path`
+pub fn generate_corpus<T: BenchTpsClient>(
+    client: &Arc<T>,
+    config: &Config,
+    keypairs: &[Keypair],
+    path: &Path,
+) -> Result<CorpusHeader> {
+    let tx_count = keypairs.len() / 2;
+    if tx_count == 0 {
+        return Err(BenchTpsError::Custom(
+            "need at least two keypairs to generate transfers".to_string(),
+        ));
+    }
+    let source_keypairs: Vec<&Keypair> = keypairs[..tx_count].iter().collect();
+    let mut dest_keypairs: VecDeque<&Keypair> = keypairs[tx_count..2 * tx_count].iter().collect();
+
+    let mut limit = config
+        .tx_limit
+        .ok_or_else(|| BenchTpsError::Custom("generate needs a transaction limit".to_string()))?;
+    let (blockhash, _) =
+        client.get_latest_blockhash_with_commitment(CommitmentConfig::processed())?;
+    let creation_slot = client.get_epoch_info()?.absolute_slot;
+
+    let (signed_with, nonce_accounts) = if config.use_durable_nonce {
+        // One round in each direction uses every keypair's nonce exactly once
+        if limit > 2 * tx_count {
+            warn!(
+                "{} keypairs can only sign {} transactions with durable nonces",
+                2 * tx_count,
+                2 * tx_count
+            );
+            limit = 2 * tx_count;
+        }
+        let authorities: Vec<&Keypair> = keypairs[..2 * tx_count].iter().collect();
+        (SignedWith::DurableNonce, nonce_accounts(&authorities))
+    } else {
+        // Every round signs with the same blockhash, so the transfers repeat once the rotation
+        // of the destinations and the direction both come back around. Anything past that
+        // would be rejected as already processed.
+        let distinct_rounds = if tx_count % 2 == 0 {
+            tx_count
+        } else {
+            2 * tx_count
+        };
+        if limit > distinct_rounds * tx_count {
+            warn!(
+                "{} keypairs can only sign {} distinct transactions with one blockhash",
+                2 * tx_count,
+                distinct_rounds * tx_count
+            );
+            limit = distinct_rounds * tx_count;
+        }
+        (SignedWith::Blockhash(blockhash), HashMap::new())
+    };
+
+    let header = CorpusHeader {
+        version: CORPUS_VERSION,
+        signed_with,
+        creation_slot,
+        transaction_count: limit as u64,
+    };
+    let mut writer = CorpusWriter::create(path, &header)?;
+
+    let start = Instant::now();
+    let mut written = 0;
+    let mut reclaim = false;
+    while written < limit {
+        let progress = written as f64 / limit as f64;
+        let compute_budget = config
+            .compute_budget
+            .instructions(config.compute_budget.price_at(progress));
+        let durable_nonces = config.use_durable_nonce.then(|| {
+            let senders: Vec<&Keypair> = if reclaim {
+                dest_keypairs.iter().copied().collect()
+            } else {
+                source_keypairs.clone()
+            };
+            get_durable_nonces(client.as_ref(), &senders, &nonce_accounts)
+        });
+        let durable_nonces = durable_nonces.transpose()?;
+        let transactions = generate_workload_txs(
+            &source_keypairs,
+            &dest_keypairs,
+            reclaim,
+            &blockhash,
+            &config.workload,
+            &compute_budget,
+            durable_nonces.as_ref(),
+        );
+        for (transaction, _timestamp) in transactions.iter().take(limit - written) {
+            writer.write(transaction)?;
+            written += 1;
+        }
+
+        // Same as a spam run: rotate so later rounds have different signatures, and switch
+        // directions so lamports flow back and forth
+        dest_keypairs.rotate_left(1);
+        reclaim = !reclaim;
+    }
+    writer.finish()?;
+
+    println!(
+        "wrote {} transactions to {:?} in {:.2}s",
+        written,
+        path,
+        duration_as_s(&start.elapsed())
+    );
+    Ok(header)
+}
+
+/// Stream the transactions of the corpus at `path` into `send_batch`, no faster than
+/// `config.rate_profile` allows, and track how many of them land. Setting `exit_signal` stops
+/// the replay after the current batch.
+pub fn replay_corpus<T: 'static + BenchTpsClient + Send + Sync>(
+    client: Arc<T>,
+    config: &Config,
+    path: &Path,
+    exit_signal: &AtomicBool,
+) -> Result<BenchTpsStats> {
+    let mut reader = CorpusReader::open(path)?;
+    let header = reader.header().clone();
+    println!(
+        "replaying {} transactions signed at slot {}",
+        header.transaction_count, header.creation_slot
+    );
+    if let SignedWith::Blockhash(_) = header.signed_with {
+        let slot = client.get_epoch_info()?.absolute_slot;
+        let age = slot.saturating_sub(header.creation_slot);
+        if age > MAX_PROCESSING_AGE as u64 {
+            warn!(
+                "corpus was signed {} slots ago, its blockhash has most likely expired",
+                age
+            );
+        }
+    }
+
+    let signature_tracker = Arc::new(SignatureTracker::default());
+    let tracker_exit_signal = Arc::new(AtomicBool::new(false));
+    let tracker_thread =
+        create_confirmation_thread(&client, &signature_tracker, &tracker_exit_signal);
+
+    let rate_limiter = config.rate_profile.map(RateLimiter::new);
+    let start = Instant::now();
+    let mut tx_sent = 0;
+    while !exit_signal.load(Ordering::Relaxed) {
+        let batch = reader.read_batch(config.replay_batch_size)?;
+        if batch.is_empty() {
+            break;
+        }
+        let batch_len = batch.len();
+        let signatures: Vec<_> = batch
+            .iter()
+            .map(|tx| (tx.signatures[0], compute_unit_price_of(tx)))
+            .collect();
+        let target_tps = match rate_limiter
+            .as_ref()
+            .map(|limiter| limiter.acquire(batch_len, exit_signal))
+        {
+            Some(None) => break,
+            target_tps => target_tps.flatten(),
+        };
+        let send_time = Instant::now();
+        match client.send_batch(batch) {
+            Ok(()) => {
+                signature_tracker.track(signatures, send_time, target_tps);
+                tx_sent += batch_len;
+            }
+            Err(err) => warn!("send_batch failed: {:?}", err),
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "sent {} transactions in {:.2}s, {:.2} tps",
+        tx_sent,
+        duration_as_s(&elapsed),
+        tx_sent as f32 / duration_as_s(&elapsed)
+    );
+
+    info!("Waiting for sent transactions to finalize...");
+    tracker_exit_signal.store(true, Ordering::Relaxed);
+    if let Err(err) = tracker_thread.join() {
+        info!("  join() failed with: {:?}", err);
+    }
+    let latency = signature_tracker.stats();
+    latency.print();
+
+    Ok(BenchTpsStats {
+        tx_sent,
+        elapsed,
+        latency,
+        ..BenchTpsStats::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::bench_tps_client::mock_client::MockClient,
+        solana_sdk::{
+            signature::{Signature, Signer},
+            system_transaction,
+        },
+        std::collections::HashSet,
+    };
+
+    #[test]
+    fn test_corpus_round_trip() {
+        let path = std::env::temp_dir().join(format!("corpus-{}.bin", std::process::id()));
+        let from = Keypair::new();
+        let blockhash = Hash::new_unique();
+        let transactions: Vec<Transaction> = (1..=5)
+            .map(|lamports| {
+                system_transaction::transfer(&from, &from.pubkey(), lamports, blockhash)
+            })
+            .collect();
+        let header = CorpusHeader {
+            version: CORPUS_VERSION,
+            signed_with: SignedWith::Blockhash(blockhash),
+            creation_slot: 42,
+            transaction_count: transactions.len() as u64,
+        };
+
+        let mut writer = CorpusWriter::create(&path, &header).unwrap();
+        for transaction in &transactions {
+            writer.write(transaction).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = CorpusReader::open(&path).unwrap();
+        assert_eq!(reader.header(), &header);
+        assert_eq!(reader.read_batch(3).unwrap(), transactions[..3]);
+        assert_eq!(reader.read_batch(3).unwrap(), transactions[3..]);
+        assert!(reader.read_batch(3).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_generate_corpus_stops_at_distinct_transactions() {
+        let path = std::env::temp_dir().join(format!("corpus-gen-{}.bin", std::process::id()));
+        let client = Arc::new(MockClient::default());
+        let config = Config {
+            tx_limit: Some(100),
+            ..Config::default()
+        };
+        let keypairs: Vec<Keypair> = (0..4).map(|_| Keypair::new()).collect();
+
+        // Two sources and two destinations only make two distinct rounds of two transfers
+        let header = generate_corpus(&client, &config, &keypairs, &path).unwrap();
+        assert_eq!(header.transaction_count, 4);
+        let transactions = CorpusReader::open(&path).unwrap().read_batch(100).unwrap();
+        let signatures: HashSet<Signature> =
+            transactions.iter().map(|tx| tx.signatures[0]).collect();
+        assert_eq!(signatures.len(), 4);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            generate_corpus(&client, &config, &keypairs[..1], &path),
+            Err(BenchTpsError::Custom(_))
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+use {
+    crate::bench::{funding_tree_size, generate_keypairs},
+    serde::{Deserialize, Serialize},
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+    },
+    std::{
+        error,
+        fs::{self, File, OpenOptions},
+        io::{self, BufReader, BufWriter, Write},
+        path::Path,
+    },
+};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const KEYPAIRS_FILE: &str = "keypairs.json";
+const STORE_VERSION: u32 = 1;
+
+/// What a keypair store holds and what it was funded with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeypairManifest {
+    pub version: u32,
+    /// Funding keypair the keypairs were derived from, and that defunding returns lamports to
+    pub seed_pubkey: String,
+    /// Namespace mixed into the derivation, if any
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Keypairs a run uses, not counting the extra keypairs that relay funding to them
+    pub keypair_count: usize,
+    pub extra: u64,
+    /// Lamports each keypair was last funded to, rent included. 0 if it never was.
+    pub lamports_per_account: u64,
+    /// Whether the keypairs were last funded with nonce accounts
+    pub durable_nonce: bool,
+}
+
+impl KeypairManifest {
+    pub fn seed_pubkey(&self) -> io::Result<Pubkey> {
+        self.seed_pubkey
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid seed pubkey {}", self.seed_pubkey)))
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Whether `dir` holds a keypair store
+pub fn store_exists(dir: &Path) -> bool {
+    dir.join(MANIFEST_FILE).exists()
+}
+
+pub fn read_manifest(dir: &Path) -> io::Result<KeypairManifest> {
+    let file = File::open(dir.join(MANIFEST_FILE))?;
+    let manifest: KeypairManifest = serde_json::from_reader(BufReader::new(file))?;
+    if manifest.version != STORE_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported keypair store version {}",
+            manifest.version
+        )));
+    }
+    Ok(manifest)
+}
+
+fn write_manifest(dir: &Path, manifest: &KeypairManifest) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+    serde_json::to_writer_pretty(&mut writer, manifest)?;
+    writer.flush()
+}
+
+/// Every keypair in the store, the extras included
+pub fn read_keypairs(dir: &Path) -> io::Result<Vec<Keypair>> {
+    let file = File::open(dir.join(KEYPAIRS_FILE))?;
+    let keypairs: Vec<Vec<u8>> = serde_json::from_reader(BufReader::new(file))?;
+    keypairs
+        .iter()
+        .map(|bytes| Keypair::from_bytes(bytes).map_err(|err| invalid_data(err.to_string())))
+        .collect()
+}
+
+/// Write `keypairs` and their manifest to a new store in `dir`. Keypairs are written as JSON
+/// byte arrays, the same as `solana-keygen` keypair files, readable only by the owner.
+pub fn save_keypairs(
+    dir: &Path,
+    manifest: &KeypairManifest,
+    keypairs: &[Keypair],
+) -> io::Result<()> {
+    if store_exists(dir) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{:?} already holds a keypair store", dir),
+        ));
+    }
+    fs::create_dir_all(dir)?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut writer = BufWriter::new(options.open(dir.join(KEYPAIRS_FILE))?);
+    let bytes: Vec<Vec<u8>> = keypairs.iter().map(|k| k.to_bytes().to_vec()).collect();
+    serde_json::to_writer(&mut writer, &bytes)?;
+    writer.flush()?;
+
+    // The manifest goes last, so a store only exists once its keypairs are complete
+    write_manifest(dir, manifest)
+}
+
+/// Record that the keypairs in `dir` were funded
+pub fn record_funding(
+    dir: &Path,
+    lamports_per_account: u64,
+    durable_nonce: bool,
+) -> io::Result<()> {
+    let mut manifest = read_manifest(dir)?;
+    manifest.lamports_per_account = lamports_per_account;
+    manifest.durable_nonce = durable_nonce;
+    write_manifest(dir, &manifest)
+}
+
+/// Refuse a store in `dir` whose keypairs weren't derived from `funding_key` and `namespace`
+pub fn check_store_owner(
+    dir: &Path,
+    manifest: &KeypairManifest,
+    funding_key: &Keypair,
+    namespace: Option<&str>,
+) -> io::Result<()> {
+    if manifest.seed_pubkey()? != funding_key.pubkey() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "keypair store {:?} belongs to funding key {}, not {}",
+                dir,
+                manifest.seed_pubkey,
+                funding_key.pubkey()
+            ),
+        ));
+    }
+    if manifest.namespace.as_deref() != namespace {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "keypair store {:?} belongs to namespace {:?}, not {:?}",
+                dir, manifest.namespace, namespace
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The first `keypair_count` keypairs of the store in `dir` plus the extras that fund them,
+/// like `generate_keypairs` returns
+pub fn load_keypairs(
+    dir: &Path,
+    funding_key: &Keypair,
+    keypair_count: usize,
+    namespace: Option<&str>,
+) -> io::Result<(Vec<Keypair>, u64)> {
+    let manifest = read_manifest(dir)?;
+    check_store_owner(dir, &manifest, funding_key, namespace)?;
+    let mut keypairs = read_keypairs(dir)?;
+    // Keypairs come from one deterministic sequence, so a smaller run uses a prefix of it
+    let (total_keys, extra) = funding_tree_size(keypair_count as u64);
+    if (keypairs.len() as u64) < total_keys {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "keypair store {:?} holds {} keypairs, {} are needed to fund {}",
+                dir,
+                keypairs.len(),
+                total_keys,
+                keypair_count
+            ),
+        ));
+    }
+    keypairs.truncate(total_keys as usize);
+    Ok((keypairs, extra))
+}
+
+/// Keypairs for a run of `keypair_count`, from the store in `dir` if there is one. Otherwise
+/// they're derived from `funding_key` and, if `dir` is given, saved to a new store there.
+pub fn load_or_generate_keypairs(
+    funding_key: &Keypair,
+    keypair_count: usize,
+    namespace: Option<&str>,
+    dir: Option<&Path>,
+) -> io::Result<(Vec<Keypair>, u64)> {
+    match dir {
+        Some(dir) if store_exists(dir) => {
+            println!("loading keypairs from {:?}", dir);
+            load_keypairs(dir, funding_key, keypair_count, namespace)
+        }
+        Some(dir) => {
+            let (keypairs, extra) = generate_keypairs(funding_key, keypair_count as u64, namespace);
+            let manifest = KeypairManifest {
+                version: STORE_VERSION,
+                seed_pubkey: funding_key.pubkey().to_string(),
+                namespace: namespace.map(str::to_string),
+                keypair_count,
+                extra,
+                lamports_per_account: 0,
+                durable_nonce: false,
+            };
+            save_keypairs(dir, &manifest, &keypairs)?;
+            println!("saved {} keypairs to {:?}", keypairs.len(), dir);
+            Ok((keypairs, extra))
+        }
+        None => Ok(generate_keypairs(
+            funding_key,
+            keypair_count as u64,
+            namespace,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pubkeys(keypairs: &[Keypair]) -> Vec<Pubkey> {
+        keypairs.iter().map(|k| k.pubkey()).collect()
+    }
+
+    #[test]
+    fn test_keypair_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("keypair-store-{}", std::process::id()));
+        let funding_key = Keypair::new();
+
+        let (keypairs, extra) =
+            load_or_generate_keypairs(&funding_key, 20, Some("a"), Some(&dir)).unwrap();
+        assert_eq!(read_manifest(&dir).unwrap().keypair_count, 20);
+        record_funding(&dir, 1_000, true).unwrap();
+        assert_eq!(read_manifest(&dir).unwrap().lamports_per_account, 1_000);
+
+        let (loaded, loaded_extra) =
+            load_or_generate_keypairs(&funding_key, 20, Some("a"), Some(&dir)).unwrap();
+        assert_eq!(pubkeys(&loaded), pubkeys(&keypairs));
+        assert_eq!(loaded_extra, extra);
+
+        // A smaller run takes a prefix. A bigger one, another funder or namespace is refused
+        let (smaller, _) = load_keypairs(&dir, &funding_key, 4, Some("a")).unwrap();
+        assert_eq!(pubkeys(&smaller), pubkeys(&keypairs[..smaller.len()]));
+        assert!(load_keypairs(&dir, &funding_key, 1_000, Some("a")).is_err());
+        assert!(load_keypairs(&dir, &Keypair::new(), 20, Some("a")).is_err());
+        assert!(load_keypairs(&dir, &funding_key, 20, None).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
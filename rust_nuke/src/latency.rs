@@ -0,0 +1,518 @@
+use {
+    crate::{bench_tps_client::BenchTpsClient, rate_limit::rate_tier},
+    log::*,
+    serde::Serialize,
+    solana_sdk::{clock::DEFAULT_MS_PER_SLOT, signature::Signature},
+    solana_transaction_status::TransactionConfirmationStatus,
+    std::{
+        collections::{BTreeMap, HashMap},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread::{sleep, Builder, JoinHandle},
+        time::{Duration, Instant},
+    },
+};
+
+// getSignatureStatuses rejects requests for more signatures than this
+pub(crate) const MAX_SIGNATURES_PER_STATUS_REQUEST: usize = 256;
+
+// A signature that hasn't been finalized this long after it was sent is given up on. This is
+// comfortably past the blockhash expiry plus the time it takes to root a slot.
+const SIGNATURE_TIMEOUT: Duration = Duration::from_secs(90);
+
+// Upper bounds of the latency histogram buckets, in milliseconds. Anything slower lands in a
+// final unbounded bucket.
+const HISTOGRAM_BUCKETS_MS: [u64; 10] = [
+    100, 200, 400, 800, 1_600, 3_200, 6_400, 12_800, 25_600, 51_200,
+];
+
+struct PendingSignature {
+    sent: Instant,
+    compute_unit_price: u64,
+    target_tps: Option<u64>,
+    processed: Option<Duration>,
+    confirmed: Option<Duration>,
+    failed: bool,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    pending: HashMap<Signature, PendingSignature>,
+    tracked: usize,
+    processed: Vec<Duration>,
+    confirmed: Vec<Duration>,
+    finalized: Vec<Duration>,
+    failed: usize,
+    dropped: usize,
+    // (sent, confirmed) per compute unit price
+    fee_tiers: BTreeMap<u64, (usize, usize)>,
+    // (sent, confirmed) per rate limiter target, bucketed by `rate_tier`
+    rate_tiers: BTreeMap<u64, (usize, usize)>,
+}
+
+impl TrackerState {
+    fn finish(&mut self, signature: &Signature, finalized: Option<Duration>) {
+        if let Some(pending) = self.pending.remove(signature) {
+            match pending.processed {
+                Some(processed) => self.processed.push(processed),
+                None => self.dropped += 1,
+            }
+            if let Some(confirmed) = pending.confirmed {
+                self.confirmed.push(confirmed);
+                self.fee_tiers
+                    .entry(pending.compute_unit_price)
+                    .or_default()
+                    .1 += 1;
+                if let Some(target_tps) = pending.target_tps {
+                    self.rate_tiers.entry(target_tps).or_default().1 += 1;
+                }
+            }
+            if let Some(finalized) = finalized {
+                self.finalized.push(finalized);
+            }
+            if pending.failed {
+                self.failed += 1;
+            }
+        }
+    }
+}
+
+/// Records when each signature was sent and when it reached each commitment level
+#[derive(Default)]
+pub struct SignatureTracker {
+    state: Mutex<TrackerState>,
+}
+
+impl SignatureTracker {
+    /// Start tracking signatures sent at `sent`, each paired with the compute unit price its
+    /// transaction pays. `target_tps` is the rate limiter's target when they were sent, if any.
+    pub fn track(
+        &self,
+        signatures: impl IntoIterator<Item = (Signature, u64)>,
+        sent: Instant,
+        target_tps: Option<f64>,
+    ) {
+        let target_tps = target_tps.map(rate_tier);
+        let mut state = self.state.lock().unwrap();
+        for (signature, compute_unit_price) in signatures {
+            state.fee_tiers.entry(compute_unit_price).or_default().0 += 1;
+            if let Some(target_tps) = target_tps {
+                state.rate_tiers.entry(target_tps).or_default().0 += 1;
+            }
+            state.pending.insert(
+                signature,
+                PendingSignature {
+                    sent,
+                    compute_unit_price,
+                    target_tps,
+                    processed: None,
+                    confirmed: None,
+                    failed: false,
+                },
+            );
+            state.tracked += 1;
+        }
+    }
+
+    fn has_pending(&self) -> bool {
+        !self.state.lock().unwrap().pending.is_empty()
+    }
+
+    /// Query the status of every pending signature once
+    fn poll<T: BenchTpsClient>(&self, client: &T) {
+        let signatures: Vec<Signature> =
+            self.state.lock().unwrap().pending.keys().cloned().collect();
+
+        for chunk in signatures.chunks(MAX_SIGNATURES_PER_STATUS_REQUEST) {
+            let statuses = match client.get_signature_statuses(chunk) {
+                Ok(statuses) => statuses,
+                Err(err) => {
+                    warn!("failed to get signature statuses: {:?}", err);
+                    continue;
+                }
+            };
+            let now = Instant::now();
+            let mut state = self.state.lock().unwrap();
+            for (signature, status) in chunk.iter().zip(statuses) {
+                let (elapsed, finalized) = match state.pending.get_mut(signature) {
+                    Some(pending) => {
+                        let elapsed = now.duration_since(pending.sent);
+                        let finalized = status.map_or(false, |status| {
+                            pending.failed |= status.err.is_some();
+                            // A signature can jump several levels between two polls, so credit
+                            // every level it has reached
+                            let level = status.confirmation_status();
+                            pending.processed.get_or_insert(elapsed);
+                            if level != TransactionConfirmationStatus::Processed {
+                                pending.confirmed.get_or_insert(elapsed);
+                            }
+                            level == TransactionConfirmationStatus::Finalized
+                        });
+                        (elapsed, finalized)
+                    }
+                    None => continue,
+                };
+                if finalized {
+                    state.finish(signature, Some(elapsed));
+                } else if elapsed > SIGNATURE_TIMEOUT {
+                    state.finish(signature, None);
+                }
+            }
+        }
+    }
+
+    /// Give up on everything still pending
+    fn abandon_pending(&self) {
+        let mut state = self.state.lock().unwrap();
+        let signatures: Vec<Signature> = state.pending.keys().cloned().collect();
+        for signature in &signatures {
+            state.finish(signature, None);
+        }
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        let state = self.state.lock().unwrap();
+        LatencyStats {
+            tracked: state.tracked,
+            processed: LatencySummary::new(&state.processed),
+            confirmed: LatencySummary::new(&state.confirmed),
+            finalized: LatencySummary::new(&state.finalized),
+            failed: state.failed,
+            dropped: state.dropped,
+            pending: state.pending.len(),
+            fee_tiers: state
+                .fee_tiers
+                .iter()
+                .map(|(compute_unit_price, (sent, confirmed))| FeeTierStats {
+                    compute_unit_price: *compute_unit_price,
+                    sent: *sent,
+                    confirmed: *confirmed,
+                    landing_rate: landing_rate(*sent, *confirmed),
+                })
+                .collect(),
+            rate_tiers: state
+                .rate_tiers
+                .iter()
+                .map(|(target_tps, (sent, confirmed))| RateTierStats {
+                    target_tps: *target_tps,
+                    sent: *sent,
+                    confirmed: *confirmed,
+                    landing_rate: landing_rate(*sent, *confirmed),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn landing_rate(sent: usize, confirmed: usize) -> f32 {
+    if sent > 0 {
+        confirmed as f32 / sent as f32
+    } else {
+        0.0
+    }
+}
+
+/// Poll the statuses of tracked signatures until `exit_signal` is set and every pending
+/// signature has either landed or timed out
+pub fn create_confirmation_thread<T>(
+    client: &Arc<T>,
+    tracker: &Arc<SignatureTracker>,
+    exit_signal: &Arc<AtomicBool>,
+) -> JoinHandle<()>
+where
+    T: 'static + BenchTpsClient + Send + Sync,
+{
+    let client = client.clone();
+    let tracker = tracker.clone();
+    let exit_signal = exit_signal.clone();
+    Builder::new()
+        .name("solana-signature-tracker".to_string())
+        .spawn(move || {
+            let mut exit_time = None;
+            loop {
+                tracker.poll(client.as_ref());
+                if exit_signal.load(Ordering::Relaxed) {
+                    if !tracker.has_pending() {
+                        break;
+                    }
+                    // Every signature times out on its own, but don't wait forever on an RPC
+                    // node that has stopped answering
+                    let exit_time = *exit_time.get_or_insert_with(Instant::now);
+                    if exit_time.elapsed() > SIGNATURE_TIMEOUT {
+                        tracker.abandon_pending();
+                        break;
+                    }
+                }
+                sleep(Duration::from_millis(DEFAULT_MS_PER_SLOT));
+            }
+        })
+        .unwrap()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HistogramBucket {
+    /// Inclusive upper bound of the bucket, `None` for the final unbounded bucket
+    pub le_ms: Option<u64>,
+    pub count: usize,
+}
+
+/// Latency distribution for one commitment level
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+    pub histogram: Vec<HistogramBucket>,
+}
+
+impl LatencySummary {
+    fn new(latencies: &[Duration]) -> Self {
+        let mut latencies_ms: Vec<u64> = latencies.iter().map(|d| d.as_millis() as u64).collect();
+        latencies_ms.sort_unstable();
+
+        let mut histogram: Vec<HistogramBucket> = HISTOGRAM_BUCKETS_MS
+            .iter()
+            .map(|le_ms| HistogramBucket {
+                le_ms: Some(*le_ms),
+                count: 0,
+            })
+            .chain(std::iter::once(HistogramBucket::default()))
+            .collect();
+        for latency_ms in &latencies_ms {
+            let bucket = HISTOGRAM_BUCKETS_MS
+                .iter()
+                .position(|le_ms| latency_ms <= le_ms)
+                .unwrap_or(HISTOGRAM_BUCKETS_MS.len());
+            histogram[bucket].count += 1;
+        }
+
+        Self {
+            count: latencies_ms.len(),
+            p50_ms: percentile(&latencies_ms, 50),
+            p90_ms: percentile(&latencies_ms, 90),
+            p99_ms: percentile(&latencies_ms, 99),
+            max_ms: latencies_ms.last().copied().unwrap_or(0),
+            histogram,
+        }
+    }
+}
+
+/// Nearest-rank percentile of already sorted values
+fn percentile(sorted: &[u64], percentile: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (percentile * sorted.len() + 99) / 100;
+    sorted[rank.saturating_sub(1)]
+}
+
+/// How many transactions paying one compute unit price landed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeeTierStats {
+    /// Micro-lamports per compute unit, 0 for transactions without a priority fee
+    pub compute_unit_price: u64,
+    pub sent: usize,
+    pub confirmed: usize,
+    pub landing_rate: f32,
+}
+
+/// How many transactions sent while the rate limiter targeted one rate landed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RateTierStats {
+    /// Target rate, rounded to two significant digits
+    pub target_tps: u64,
+    pub sent: usize,
+    pub confirmed: usize,
+    pub landing_rate: f32,
+}
+
+/// Time from send to each commitment level, for every signature sent during a run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyStats {
+    pub tracked: usize,
+    pub processed: LatencySummary,
+    pub confirmed: LatencySummary,
+    pub finalized: LatencySummary,
+    /// Transactions that landed with an error
+    pub failed: usize,
+    /// Transactions that were never seen by the cluster
+    pub dropped: usize,
+    /// Transactions still in flight when tracking stopped
+    pub pending: usize,
+    /// Landing rate broken down by priority fee, in ascending price order
+    pub fee_tiers: Vec<FeeTierStats>,
+    /// Landing rate broken down by target send rate, in ascending rate order. Empty unless
+    /// the run was rate limited.
+    pub rate_tiers: Vec<RateTierStats>,
+}
+
+impl LatencyStats {
+    pub fn print(&self) {
+        println!(
+            "\nConfirmation latency for {} signatures ({} failed, {} dropped, {} pending)",
+            self.tracked, self.failed, self.dropped, self.pending
+        );
+        println!(" Commitment |   Count |  p50 ms |  p90 ms |  p99 ms |  max ms");
+        println!("------------+---------+---------+---------+---------+--------");
+        for (name, summary) in [
+            ("processed", &self.processed),
+            ("confirmed", &self.confirmed),
+            ("finalized", &self.finalized),
+        ] {
+            println!(
+                " {:10} | {:7} | {:7} | {:7} | {:7} | {:7}",
+                name, summary.count, summary.p50_ms, summary.p90_ms, summary.p99_ms, summary.max_ms
+            );
+        }
+
+        println!("\nConfirmed latency histogram");
+        for bucket in &self.confirmed.histogram {
+            let label = match bucket.le_ms {
+                Some(le_ms) => format!("<= {} ms", le_ms),
+                None => "slower".to_string(),
+            };
+            println!(" {:>12} | {}", label, bucket.count);
+        }
+
+        if self
+            .fee_tiers
+            .iter()
+            .any(|tier| tier.compute_unit_price > 0)
+        {
+            println!("\n CU price (micro-lamports) |    Sent | Confirmed | Landing rate");
+            println!("---------------------------+---------+-----------+-------------");
+            for tier in &self.fee_tiers {
+                println!(
+                    " {:25} | {:7} | {:9} | {:11.2}%",
+                    tier.compute_unit_price,
+                    tier.sent,
+                    tier.confirmed,
+                    tier.landing_rate * 100.0
+                );
+            }
+        }
+
+        if !self.rate_tiers.is_empty() {
+            println!("\n Target TPS |    Sent | Confirmed | Landing rate");
+            println!("------------+---------+-----------+-------------");
+            for tier in &self.rate_tiers {
+                println!(
+                    " {:10} | {:7} | {:9} | {:11.2}%",
+                    tier.target_tps,
+                    tier.sent,
+                    tier.confirmed,
+                    tier.landing_rate * 100.0
+                );
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_runtime::{bank::Bank, bank_client::BankClient},
+        solana_sdk::{
+            client::SyncClient, genesis_config::create_genesis_config, message::Message,
+            native_token::sol_to_lamports, pubkey::Pubkey, signature::Signer, system_instruction,
+        },
+    };
+
+    #[test]
+    fn test_percentiles() {
+        assert_eq!(percentile(&[], 50), 0);
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[7], 99), 7);
+
+        let hundred: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&hundred, 50), 50);
+        assert_eq!(percentile(&hundred, 90), 90);
+        assert_eq!(percentile(&hundred, 99), 99);
+
+        // Nearest rank rounds up, so the top percentiles of a few samples are the slowest one
+        let ten: Vec<u64> = (1..=10).collect();
+        assert_eq!(percentile(&ten, 50), 5);
+        assert_eq!(percentile(&ten, 90), 9);
+        assert_eq!(percentile(&ten, 99), 10);
+    }
+
+    #[test]
+    fn test_latency_summary() {
+        let empty = LatencySummary::new(&[]);
+        assert_eq!((empty.count, empty.p50_ms, empty.max_ms), (0, 0, 0));
+        assert_eq!(empty.histogram.len(), HISTOGRAM_BUCKETS_MS.len() + 1);
+
+        let millis = Duration::from_millis;
+        let summary = LatencySummary::new(&[millis(101), millis(100), millis(60_000)]);
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.p50_ms, 101);
+        assert_eq!(summary.p99_ms, 60_000);
+        assert_eq!(summary.max_ms, 60_000);
+        // Bucket bounds are inclusive, and anything past the last one is unbounded
+        let counts: Vec<usize> = summary.histogram.iter().map(|b| b.count).collect();
+        assert_eq!(counts, [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(summary.histogram.last().unwrap().le_ms, None);
+    }
+
+    #[test]
+    fn test_signature_tracker() {
+        let (genesis_config, payer) = create_genesis_config(sol_to_lamports(1.0));
+        let client = BankClient::new(Bank::new_for_tests(&genesis_config));
+        let instruction = system_instruction::transfer(
+            &payer.pubkey(),
+            &Pubkey::new_unique(),
+            sol_to_lamports(0.1),
+        );
+        let message = Message::new(&[instruction], Some(&payer.pubkey()));
+        let landed = client.send_and_confirm_message(&[&payer], message).unwrap();
+
+        let tracker = SignatureTracker::default();
+        tracker.track(
+            [(landed, 0), (Signature::new_unique(), 100)],
+            Instant::now(),
+            None,
+        );
+        tracker.poll(&client);
+        let stats = tracker.stats();
+        assert_eq!(stats.tracked, 2);
+        // The bank has no forks, so whatever it processed is already finalized
+        assert_eq!(stats.finalized.count, 1);
+        assert_eq!(stats.confirmed.count, 1);
+        // The unknown signature was sent too recently to give up on
+        assert_eq!((stats.dropped, stats.pending), (0, 1));
+        let fee_tiers: Vec<_> = stats
+            .fee_tiers
+            .iter()
+            .map(|tier| (tier.compute_unit_price, tier.landing_rate))
+            .collect();
+        assert_eq!(fee_tiers, [(0, 1.0), (100, 0.0)]);
+        assert!(stats.rate_tiers.is_empty());
+
+        tracker.abandon_pending();
+        let stats = tracker.stats();
+        assert_eq!((stats.dropped, stats.pending), (1, 0));
+    }
+
+    #[test]
+    fn test_signature_tracker_expiry() {
+        // A freshly booted host's monotonic clock may not reach back past the timeout
+        let expired = match Instant::now().checked_sub(SIGNATURE_TIMEOUT + Duration::from_secs(1)) {
+            Some(expired) => expired,
+            None => return,
+        };
+        let (genesis_config, _payer) = create_genesis_config(sol_to_lamports(1.0));
+        let client = BankClient::new(Bank::new_for_tests(&genesis_config));
+
+        let tracker = SignatureTracker::default();
+        tracker.track([(Signature::new_unique(), 0)], expired, Some(1234.0));
+        tracker.poll(&client);
+        let stats = tracker.stats();
+        assert_eq!((stats.dropped, stats.pending), (1, 0));
+        assert_eq!(stats.rate_tiers[0].target_tps, 1200);
+        assert_eq!(stats.rate_tiers[0].landing_rate, 0.0);
+    }
+}
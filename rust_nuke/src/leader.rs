@@ -0,0 +1,342 @@
+use {
+    crate::bench_tps_client::{BenchTpsError, Result},
+    log::*,
+    solana_client::{
+        pubsub_client::{PubsubClient, PubsubClientSubscription},
+        rpc_client::RpcClient,
+        rpc_response::SlotUpdate,
+    },
+    solana_sdk::{
+        clock::{Epoch, Slot, NUM_CONSECUTIVE_LEADER_SLOTS},
+        commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
+    },
+    std::{
+        collections::{HashMap, HashSet, VecDeque},
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, RwLock,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+const MAX_RECENT_SLOTS: usize = 12;
+
+// Unlikely that 12 leaders in a row miss their slots
+const MAX_SLOT_SKIP_DISTANCE: u64 = 48;
+
+// Nodes can leave the cluster or change their ports, so their addresses are fetched again
+// after this long
+const CLUSTER_NODES_REFRESH: Duration = Duration::from_secs(5 * 60);
+
+// A leader can hold another run of slots shortly after its first, so look a little further
+// ahead than one run per leader asked for
+const SEARCH_SLOTS_PER_LEADER: u64 = 2 * NUM_CONSECUTIVE_LEADER_SLOTS;
+
+/// Estimates the slot the cluster is on from the slots validators recently reported
+pub struct RecentLeaderSlots(VecDeque<Slot>);
+
+impl RecentLeaderSlots {
+    pub fn new(current_slot: Slot) -> Self {
+        Self(VecDeque::from([current_slot]))
+    }
+
+    pub fn record_slot(&mut self, slot: Slot) {
+        self.0.push_back(slot);
+        while self.0.len() > MAX_RECENT_SLOTS {
+            self.0.pop_front();
+        }
+    }
+
+    /// The highest recent slot that's in line with the others. A validator can broadcast
+    /// blocks for slots far in the future, which mustn't be taken for the current slot.
+    pub fn estimated_current_slot(&self) -> Slot {
+        let mut recent_slots: Vec<Slot> = self.0.iter().copied().collect();
+        recent_slots.sort_unstable();
+        let max_index = recent_slots.len() - 1;
+        let median_index = max_index / 2;
+        let expected_current_slot = recent_slots[median_index] + (max_index - median_index) as u64;
+        let max_reasonable_current_slot = expected_current_slot + MAX_SLOT_SKIP_DISTANCE;
+        recent_slots
+            .into_iter()
+            .rev()
+            .find(|slot| *slot <= max_reasonable_current_slot)
+            // The median itself is always reasonable
+            .unwrap_or_default()
+    }
+}
+
+struct EpochLeaders {
+    first_slot: Slot,
+    leaders: Vec<Pubkey>,
+}
+
+/// Slot leaders of the epochs fetched so far
+#[derive(Default)]
+pub struct LeaderSchedule {
+    epochs: HashMap<Epoch, EpochLeaders>,
+}
+
+impl LeaderSchedule {
+    /// Add the leaders of `epoch`, one per slot starting at `first_slot`
+    pub fn insert(&mut self, epoch: Epoch, first_slot: Slot, leaders: Vec<Pubkey>) {
+        self.epochs.insert(
+            epoch,
+            EpochLeaders {
+                first_slot,
+                leaders,
+            },
+        );
+    }
+
+    pub fn slot_leader(&self, slot: Slot) -> Option<Pubkey> {
+        self.epochs.values().find_map(|epoch| {
+            let index = slot.checked_sub(epoch.first_slot)?;
+            epoch.leaders.get(index as usize).copied()
+        })
+    }
+
+    pub fn covers(&self, slot: Slot) -> bool {
+        self.slot_leader(slot).is_some()
+    }
+
+    /// Forget epochs that ended before `slot`
+    fn prune(&mut self, slot: Slot) {
+        self.epochs
+            .retain(|_, epoch| epoch.first_slot + epoch.leaders.len() as u64 > slot);
+    }
+}
+
+/// Fetch the leaders of the epoch `slot` is in, which may be the epoch after the current one
+pub fn fetch_epoch_leaders(
+    rpc_client: &RpcClient,
+    slot: Slot,
+) -> Result<(Epoch, Slot, Vec<Pubkey>)> {
+    let epoch_info = rpc_client.get_epoch_info()?;
+    let current_first_slot = epoch_info.absolute_slot - epoch_info.slot_index;
+    let epochs_ahead = slot.saturating_sub(current_first_slot) / epoch_info.slots_in_epoch;
+    let epoch = epoch_info.epoch + epochs_ahead;
+    let first_slot = current_first_slot + epochs_ahead * epoch_info.slots_in_epoch;
+
+    let schedule = rpc_client
+        .get_leader_schedule(Some(first_slot))?
+        .ok_or_else(|| BenchTpsError::Custom(format!("no leader schedule for epoch {}", epoch)))?;
+    let mut leaders = vec![Pubkey::default(); epoch_info.slots_in_epoch as usize];
+    for (leader, slot_indexes) in schedule {
+        let leader: Pubkey = leader
+            .parse()
+            .map_err(|_| BenchTpsError::Custom(format!("invalid leader pubkey {}", leader)))?;
+        for index in slot_indexes {
+            if let Some(slot_leader) = leaders.get_mut(index) {
+                *slot_leader = leader;
+            }
+        }
+    }
+    Ok((epoch, first_slot, leaders))
+}
+
+/// TPU address of every node in the cluster that advertises one
+pub fn fetch_leader_tpus(rpc_client: &RpcClient) -> Result<HashMap<Pubkey, SocketAddr>> {
+    Ok(rpc_client
+        .get_cluster_nodes()?
+        .into_iter()
+        .filter_map(|node| Some((node.pubkey.parse().ok()?, node.tpu?)))
+        .collect())
+}
+
+/// Slot the validator that sent `update` is working on, if the update says
+fn updated_slot(update: &SlotUpdate, received_shreds: &AtomicBool) -> Option<Slot> {
+    match update {
+        SlotUpdate::FirstShredReceived { slot, .. } => {
+            received_shreds.store(true, Ordering::Relaxed);
+            Some(*slot)
+        }
+        SlotUpdate::Completed { slot, .. } => {
+            received_shreds.store(true, Ordering::Relaxed);
+            Some(slot + 1)
+        }
+        // A single node cluster's leader sends no shred notifications, so fall back to banks
+        SlotUpdate::CreatedBank { slot, .. } if !received_shreds.load(Ordering::Relaxed) => {
+            Some(*slot)
+        }
+        _ => None,
+    }
+}
+
+/// Follows the current slot and who leads the upcoming ones, so transactions can be sent
+/// straight to the TPUs of the next leaders
+pub struct LeaderTracker {
+    rpc_client: Arc<RpcClient>,
+    recent_slots: Arc<RwLock<RecentLeaderSlots>>,
+    schedule: RwLock<LeaderSchedule>,
+    tpus: RwLock<(Instant, HashMap<Pubkey, SocketAddr>)>,
+    _subscription: Option<PubsubClientSubscription<SlotUpdate>>,
+}
+
+impl LeaderTracker {
+    /// Start tracking slots through the slot updates of the pubsub endpoint at `websocket_url`
+    pub fn new(rpc_client: Arc<RpcClient>, websocket_url: &str) -> Result<Self> {
+        let current_slot = rpc_client.get_slot_with_commitment(CommitmentConfig::processed())?;
+        let recent_slots = Arc::new(RwLock::new(RecentLeaderSlots::new(current_slot)));
+        let subscription = {
+            let recent_slots = recent_slots.clone();
+            let received_shreds = AtomicBool::new(false);
+            PubsubClient::slot_updates_subscribe(websocket_url, move |update| {
+                if let Some(slot) = updated_slot(&update, &received_shreds) {
+                    recent_slots.write().unwrap().record_slot(slot);
+                }
+            })
+            .map_err(|err| {
+                BenchTpsError::Custom(format!("slot updates subscription failed: {}", err))
+            })?
+        };
+
+        let mut schedule = LeaderSchedule::default();
+        let (epoch, first_slot, leaders) = fetch_epoch_leaders(&rpc_client, current_slot)?;
+        schedule.insert(epoch, first_slot, leaders);
+        let tpus = fetch_leader_tpus(&rpc_client)?;
+        info!(
+            "tracking leaders from slot {}, {} nodes with a TPU",
+            current_slot,
+            tpus.len()
+        );
+
+        Ok(Self {
+            rpc_client,
+            recent_slots,
+            schedule: RwLock::new(schedule),
+            tpus: RwLock::new((Instant::now(), tpus)),
+            _subscription: Some(subscription),
+        })
+    }
+
+    pub fn current_slot(&self) -> Slot {
+        self.recent_slots.read().unwrap().estimated_current_slot()
+    }
+
+    /// Record a slot reported by something other than the slot updates subscription
+    pub fn record_slot(&self, slot: Slot) {
+        self.recent_slots.write().unwrap().record_slot(slot);
+    }
+
+    pub fn slot_leader(&self, slot: Slot) -> Option<Pubkey> {
+        self.ensure_schedule(slot);
+        self.schedule.read().unwrap().slot_leader(slot)
+    }
+
+    /// TPU sockets of the next `count` distinct leaders, starting with the current one.
+    /// Leaders that don't advertise a TPU are skipped.
+    pub fn leader_tpu_sockets(&self, count: usize) -> Vec<SocketAddr> {
+        let current_slot = self.current_slot();
+        let last_slot = current_slot + count as u64 * SEARCH_SLOTS_PER_LEADER;
+        self.ensure_schedule(current_slot);
+        self.ensure_schedule(last_slot);
+        self.refresh_tpus_if_stale();
+
+        let schedule = self.schedule.read().unwrap();
+        let tpus = self.tpus.read().unwrap();
+        let mut leaders = HashSet::new();
+        let mut sockets = Vec::with_capacity(count);
+        for slot in current_slot..=last_slot {
+            if sockets.len() == count {
+                break;
+            }
+            let leader = match schedule.slot_leader(slot) {
+                Some(leader) => leader,
+                None => continue,
+            };
+            if leaders.insert(leader) {
+                match tpus.1.get(&leader) {
+                    Some(tpu) => sockets.push(*tpu),
+                    None => debug!("no TPU address for leader {}", leader),
+                }
+            }
+        }
+        sockets
+    }
+
+    /// Fetch the leaders of the epoch `slot` is in if they aren't known yet. Failures only
+    /// leave those slots without a leader.
+    fn ensure_schedule(&self, slot: Slot) {
+        if self.schedule.read().unwrap().covers(slot) {
+            return;
+        }
+        match fetch_epoch_leaders(&self.rpc_client, slot) {
+            Ok((epoch, first_slot, leaders)) => {
+                let mut schedule = self.schedule.write().unwrap();
+                schedule.prune(self.current_slot());
+                schedule.insert(epoch, first_slot, leaders);
+            }
+            Err(err) => warn!(
+                "Couldn't get the leader schedule for slot {}: {}",
+                slot, err
+            ),
+        }
+    }
+
+    fn refresh_tpus_if_stale(&self) {
+        if self.tpus.read().unwrap().0.elapsed() < CLUSTER_NODES_REFRESH {
+            return;
+        }
+        match fetch_leader_tpus(&self.rpc_client) {
+            Ok(tpus) => *self.tpus.write().unwrap() = (Instant::now(), tpus),
+            Err(err) => warn!("Couldn't get cluster nodes: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_leader_slots() {
+        let mut recent_slots = RecentLeaderSlots::new(100);
+        for slot in 101..106 {
+            recent_slots.record_slot(slot);
+        }
+        assert_eq!(recent_slots.estimated_current_slot(), 105);
+
+        // A slot far beyond the rest is ignored
+        recent_slots.record_slot(10_000);
+        assert_eq!(recent_slots.estimated_current_slot(), 105);
+        recent_slots.record_slot(107);
+        assert_eq!(recent_slots.estimated_current_slot(), 107);
+    }
+
+    #[test]
+    fn test_leader_tpu_sockets() {
+        let leaders: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        // Every leader holds a run of slots, the third one twice
+        let schedule: Vec<Pubkey> = [0, 1, 2, 2, 3, 2]
+            .iter()
+            .flat_map(|i| vec![leaders[*i]; NUM_CONSECUTIVE_LEADER_SLOTS as usize])
+            .collect();
+        let tpu = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        // The fourth leader has no TPU
+        let tpus = leaders[..3]
+            .iter()
+            .zip(8001..)
+            .map(|(leader, port)| (*leader, tpu(port)))
+            .collect();
+
+        let mut leader_schedule = LeaderSchedule::default();
+        leader_schedule.insert(0, 1_000, schedule);
+        let tracker = LeaderTracker {
+            rpc_client: Arc::new(RpcClient::new_mock("fails".to_string())),
+            recent_slots: Arc::new(RwLock::new(RecentLeaderSlots::new(1_004))),
+            schedule: RwLock::new(leader_schedule),
+            tpus: RwLock::new((Instant::now(), tpus)),
+            _subscription: None,
+        };
+
+        assert_eq!(tracker.slot_leader(1_004), Some(leaders[1]));
+        assert_eq!(tracker.leader_tpu_sockets(1), vec![tpu(8002)]);
+        assert_eq!(tracker.leader_tpu_sockets(3), vec![tpu(8002), tpu(8003)]);
+
+        tracker.record_slot(1_008);
+        assert_eq!(tracker.leader_tpu_sockets(2), vec![tpu(8003)]);
+    }
+}
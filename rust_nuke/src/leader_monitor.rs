@@ -0,0 +1,394 @@
+use {
+    crate::{
+        bench_tps_client::Result,
+        leader::{fetch_epoch_leaders, LeaderSchedule},
+    },
+    log::*,
+    solana_client::{rpc_client::RpcClient, rpc_config::RpcBlockConfig},
+    solana_sdk::{clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey},
+    solana_transaction_status::{
+        EncodedTransaction, TransactionDetails, UiConfirmedBlock, UiMessage, UiTransaction,
+        UiTransactionEncoding,
+    },
+    std::{
+        collections::{HashMap, HashSet},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread::{sleep, Builder, JoinHandle},
+        time::Duration,
+    },
+};
+
+// Slots are only checked once they're confirmed, so there's no point checking much more often
+// than a few slots pass
+const MONITOR_INTERVAL: Duration = Duration::from_secs(2);
+
+// Votes land as transactions too, but say nothing about how much load a leader took on
+const VOTE_PROGRAM_ID: &str = "Vote111111111111111111111111111111111111111";
+
+/// How one leader did with the slots it was assigned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LeaderStats {
+    pub assigned: u64,
+    pub produced: u64,
+    /// Non-vote transactions in the blocks produced. Votes fill most of every block whatever
+    /// the load, so they'd hide the blocks that came out small.
+    pub transactions: u64,
+}
+
+impl LeaderStats {
+    pub fn skipped(&self) -> u64 {
+        self.assigned - self.produced
+    }
+
+    pub fn skip_rate(&self) -> f64 {
+        if self.assigned == 0 {
+            0.0
+        } else {
+            self.skipped() as f64 / self.assigned as f64
+        }
+    }
+
+    pub fn average_transactions(&self) -> f64 {
+        if self.produced == 0 {
+            0.0
+        } else {
+            self.transactions as f64 / self.produced as f64
+        }
+    }
+}
+
+/// Slots assigned to and produced by each leader over a range of slots
+#[derive(Debug, Default)]
+pub struct LeaderPerformance {
+    pub first_slot: Option<Slot>,
+    pub last_slot: Option<Slot>,
+    pub leaders: HashMap<Pubkey, LeaderStats>,
+    /// Produced blocks the node failed to return, which aren't credited to any leader
+    pub unavailable_slots: u64,
+}
+
+impl LeaderPerformance {
+    /// Record `slot`, led by `leader`, with the number of transactions in its block or `None`
+    /// if it was skipped
+    pub fn record_slot(&mut self, slot: Slot, leader: Pubkey, transactions: Option<usize>) {
+        self.extend_range(slot);
+        let stats = self.leaders.entry(leader).or_default();
+        stats.assigned += 1;
+        if let Some(transactions) = transactions {
+            stats.produced += 1;
+            stats.transactions += transactions as u64;
+        }
+    }
+
+    /// Record `slot` as produced, but with a block that couldn't be fetched
+    pub fn record_unavailable(&mut self, slot: Slot) {
+        self.extend_range(slot);
+        self.unavailable_slots += 1;
+    }
+
+    fn extend_range(&mut self, slot: Slot) {
+        self.first_slot = Some(self.first_slot.map_or(slot, |first| first.min(slot)));
+        self.last_slot = Some(self.last_slot.map_or(slot, |last| last.max(slot)));
+    }
+
+    pub fn total(&self) -> LeaderStats {
+        self.leaders
+            .values()
+            .fold(LeaderStats::default(), |total, stats| LeaderStats {
+                assigned: total.assigned + stats.assigned,
+                produced: total.produced + stats.produced,
+                transactions: total.transactions + stats.transactions,
+            })
+    }
+
+    /// Leaders with the highest skip rate first, then those assigned the most slots
+    pub fn sorted_leaders(&self) -> Vec<(Pubkey, LeaderStats)> {
+        let mut leaders: Vec<_> = self.leaders.iter().map(|(k, v)| (*k, *v)).collect();
+        leaders.sort_by(|(a_key, a), (b_key, b)| {
+            b.skip_rate()
+                .total_cmp(&a.skip_rate())
+                .then(b.assigned.cmp(&a.assigned))
+                .then(a_key.cmp(b_key))
+        });
+        leaders
+    }
+
+    pub fn print(&self) {
+        let (first_slot, last_slot) = match (self.first_slot, self.last_slot) {
+            (Some(first_slot), Some(last_slot)) => (first_slot, last_slot),
+            _ => {
+                println!("\nNo confirmed slots to report leader performance for");
+                return;
+            }
+        };
+        println!(
+            "\nLeader performance over slots {}..={} ({} blocks unavailable)",
+            first_slot, last_slot, self.unavailable_slots
+        );
+        println!(
+            " {:44} | Assigned | Produced | Skip rate | Non-vote txs/block",
+            "Leader"
+        );
+        println!(
+            "{:-<46}+----------+----------+-----------+-------------------",
+            ""
+        );
+        let print_row = |name: &str, stats: &LeaderStats| {
+            println!(
+                " {:44} | {:8} | {:8} | {:8.2}% | {:18.1}",
+                name,
+                stats.assigned,
+                stats.produced,
+                stats.skip_rate() * 100.0,
+                stats.average_transactions()
+            );
+        };
+        for (leader, stats) in self.sorted_leaders() {
+            print_row(&leader.to_string(), &stats);
+        }
+        print_row("total", &self.total());
+    }
+}
+
+fn is_vote(transaction: &EncodedTransaction) -> bool {
+    match transaction {
+        EncodedTransaction::Json(UiTransaction {
+            message: UiMessage::Raw(message),
+            ..
+        }) => message.instructions.iter().any(|instruction| {
+            message
+                .account_keys
+                .get(instruction.program_id_index as usize)
+                .map_or(false, |program_id| program_id == VOTE_PROGRAM_ID)
+        }),
+        _ => false,
+    }
+}
+
+fn non_vote_transactions(block: &UiConfirmedBlock) -> usize {
+    block
+        .transactions
+        .iter()
+        .flatten()
+        .filter(|transaction| !is_vote(&transaction.transaction))
+        .count()
+}
+
+/// Record the leader of every slot from `next_slot` through `last_slot`, and whether and how
+/// big a block it produced. `next_slot` moves past each slot as it's recorded, so a failure
+/// part way leaves it at the first slot still to record. A block that can't be fetched is
+/// recorded as unavailable rather than retried, so one bad slot can't hold the monitor up.
+fn record_slots(
+    rpc_client: &RpcClient,
+    schedule: &mut LeaderSchedule,
+    performance: &mut LeaderPerformance,
+    next_slot: &mut Slot,
+    last_slot: Slot,
+) -> Result<()> {
+    let commitment = CommitmentConfig::confirmed();
+    let produced: HashSet<Slot> = rpc_client
+        .get_blocks_with_commitment(*next_slot, Some(last_slot), commitment)?
+        .into_iter()
+        .collect();
+    let config = RpcBlockConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        transaction_details: Some(TransactionDetails::Full),
+        rewards: Some(false),
+        commitment: Some(commitment),
+        ..RpcBlockConfig::default()
+    };
+    for slot in *next_slot..=last_slot {
+        if !schedule.covers(slot) {
+            let (epoch, epoch_first_slot, leaders) = fetch_epoch_leaders(rpc_client, slot)?;
+            schedule.insert(epoch, epoch_first_slot, leaders);
+        }
+        if let Some(leader) = schedule.slot_leader(slot) {
+            if !produced.contains(&slot) {
+                performance.record_slot(slot, leader, None);
+            } else {
+                match rpc_client.get_block_with_config(slot, config) {
+                    Ok(block) => {
+                        let transactions = non_vote_transactions(&block);
+                        performance.record_slot(slot, leader, Some(transactions));
+                    }
+                    Err(err) => {
+                        warn!("Couldn't get the block of slot {}: {}", slot, err);
+                        performance.record_unavailable(slot);
+                    }
+                }
+            }
+        }
+        *next_slot = slot + 1;
+    }
+    Ok(())
+}
+
+/// Compares the leader schedule against the blocks produced while it runs, to show which
+/// leaders skipped slots or produced small blocks under load
+pub struct LeaderMonitor {
+    exit_signal: Arc<AtomicBool>,
+    thread: JoinHandle<LeaderPerformance>,
+}
+
+impl LeaderMonitor {
+    /// Start following confirmed slots from the current one
+    pub fn start(rpc_client: Arc<RpcClient>) -> Result<Self> {
+        let mut next_slot = rpc_client.get_slot_with_commitment(CommitmentConfig::confirmed())?;
+        let exit_signal = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let exit_signal = exit_signal.clone();
+            Builder::new()
+                .name("solana-leader-monitor".to_string())
+                .spawn(move || {
+                    let mut schedule = LeaderSchedule::default();
+                    let mut performance = LeaderPerformance::default();
+                    loop {
+                        // Catch up once more after being stopped, then finish
+                        let exit = exit_signal.load(Ordering::Relaxed);
+                        let confirmed_slot = rpc_client
+                            .get_slot_with_commitment(CommitmentConfig::confirmed())
+                            .unwrap_or(0);
+                        if confirmed_slot >= next_slot {
+                            if let Err(err) = record_slots(
+                                &rpc_client,
+                                &mut schedule,
+                                &mut performance,
+                                &mut next_slot,
+                                confirmed_slot,
+                            ) {
+                                warn!("Couldn't check slots from {}: {}", next_slot, err)
+                            }
+                        }
+                        if exit {
+                            return performance;
+                        }
+                        sleep(MONITOR_INTERVAL);
+                    }
+                })
+                .unwrap()
+        };
+        Ok(Self {
+            exit_signal,
+            thread,
+        })
+    }
+
+    /// Stop following slots and return what was seen
+    pub fn stop(self) -> LeaderPerformance {
+        self.exit_signal.store(true, Ordering::Relaxed);
+        self.thread.join().unwrap_or_else(|err| {
+            info!("  join() failed with: {:?}", err);
+            LeaderPerformance::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json, solana_client::rpc_request::RpcRequest};
+
+    #[test]
+    fn test_leader_performance() {
+        let steady = Pubkey::new_unique();
+        let skipping = Pubkey::new_unique();
+        let mut performance = LeaderPerformance::default();
+        for slot in 0..4 {
+            performance.record_slot(slot, steady, Some(100));
+        }
+        for slot in 4..8 {
+            performance.record_slot(slot, skipping, (slot % 2 == 0).then(|| 10));
+        }
+
+        let skipping_stats = performance.leaders[&skipping];
+        assert_eq!(skipping_stats.skipped(), 2);
+        assert_eq!(skipping_stats.skip_rate(), 0.5);
+        assert_eq!(skipping_stats.average_transactions(), 10.0);
+        assert_eq!(performance.sorted_leaders()[0].0, skipping);
+        assert_eq!(
+            performance.total(),
+            LeaderStats {
+                assigned: 8,
+                produced: 6,
+                transactions: 420,
+            }
+        );
+        assert_eq!(
+            (performance.first_slot, performance.last_slot),
+            (Some(0), Some(7))
+        );
+    }
+
+    #[test]
+    fn test_record_slots() {
+        let leader = Pubkey::new_unique();
+        let payer = Pubkey::new_unique().to_string();
+        let transaction = |program_id: &str| {
+            json!({
+                "transaction": {
+                    "signatures": [],
+                    "message": {
+                        "header": {
+                            "numRequiredSignatures": 1,
+                            "numReadonlySignedAccounts": 0,
+                            "numReadonlyUnsignedAccounts": 1,
+                        },
+                        "accountKeys": [payer, program_id],
+                        "recentBlockhash": "11111111111111111111111111111111",
+                        "instructions": [{"programIdIndex": 1, "accounts": [0], "data": ""}],
+                    },
+                },
+                "meta": null,
+            })
+        };
+        let block = json!({
+            "previousBlockhash": "11111111111111111111111111111111",
+            "blockhash": "11111111111111111111111111111111",
+            "parentSlot": 9,
+            "transactions": [
+                transaction(VOTE_PROGRAM_ID),
+                transaction(VOTE_PROGRAM_ID),
+                transaction("11111111111111111111111111111111"),
+            ],
+        });
+        // Each mock answers once, after which the "fails" client answers with null, which
+        // no block parses from
+        let mocks = HashMap::from([
+            (RpcRequest::GetVersion, json!({"solana-core": "1.10.19"})),
+            (RpcRequest::GetBlocks, json!([10, 12, 13])),
+            (RpcRequest::GetBlock, block),
+        ]);
+        let rpc_client = RpcClient::new_mock_with_mocks("fails".to_string(), mocks);
+        let mut schedule = LeaderSchedule::default();
+        schedule.insert(0, 0, vec![leader; 100]);
+        let mut performance = LeaderPerformance::default();
+        let mut next_slot = 10;
+
+        record_slots(
+            &rpc_client,
+            &mut schedule,
+            &mut performance,
+            &mut next_slot,
+            13,
+        )
+        .unwrap();
+        // Slot 11 was skipped, and the blocks of 12 and 13 couldn't be fetched, which doesn't
+        // stop the monitor moving on
+        assert_eq!(next_slot, 14);
+        assert_eq!(performance.unavailable_slots, 2);
+        assert_eq!(
+            performance.leaders[&leader],
+            LeaderStats {
+                assigned: 2,
+                produced: 1,
+                transactions: 1,
+            }
+        );
+        assert_eq!(
+            (performance.first_slot, performance.last_slot),
+            (Some(10), Some(13))
+        );
+    }
+}
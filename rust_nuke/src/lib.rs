@@ -0,0 +1,19 @@
+pub mod bench;
+pub mod bench_tps_client;
+pub mod block_stats;
+pub mod blockhash;
+pub mod cli;
+pub mod corpus;
+pub mod keypair_store;
+pub mod latency;
+pub mod leader;
+pub mod leader_monitor;
+pub mod nonce;
+pub mod nuke_program;
+pub mod perf_utils;
+pub mod priority_fee;
+pub mod proxy;
+pub mod rate_limit;
+pub mod report;
+pub mod send_back;
+pub mod workload;
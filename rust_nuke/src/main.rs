@@ -1,75 +1,393 @@
 use {
-    crate::bench::*,
-    crate::bench_tps_client::*,
-    crate::send_back::*,
+    rust_nuke::bench::*,
+    rust_nuke::bench_tps_client::*,
+    rust_nuke::block_stats::collect_block_stats,
+    rust_nuke::cli::{self, Command, ExternalClientType},
+    rust_nuke::corpus::{generate_corpus, replay_corpus},
+    rust_nuke::keypair_store::{
+        load_keypairs, load_or_generate_keypairs, read_keypairs, read_manifest, store_exists,
+    },
+    rust_nuke::leader_monitor::LeaderMonitor,
+    rust_nuke::report::RunReport,
+    rust_nuke::send_back::*,
     solana_client::{
         connection_cache,
         rpc_client::RpcClient,
-        // tpu_client::{TpuClient, TpuClientConfig},
+        thin_client::create_client,
+        tpu_client::{TpuClient, TpuClientConfig},
     },
-    solana_core::gen_keys::GenKeys,
     solana_sdk::{
-        clock::{DEFAULT_MS_PER_SLOT, DEFAULT_S_PER_SLOT, MAX_PROCESSING_AGE},
         commitment_config::CommitmentConfig,
-        hash::Hash,
-        // instruction::{AccountMeta, Instruction},
-        // message::Message,
-        // native_token::Sol,
         pubkey::Pubkey,
-        signature::{read_keypair_file, Keypair, Signer},
-        // system_instruction, system_transaction,
-        // timing::{duration_as_ms, duration_as_s, duration_as_us, timestamp},
-        // transaction::Transaction,
+        signature::{Keypair, Signer},
     },
     std::{
-        // collections::{HashSet, VecDeque},
-        // process::exit,
+        collections::HashSet,
+        process::exit,
         sync::{
-            // atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering},
-            mpsc,
+            atomic::{AtomicBool, Ordering},
             Arc,
         },
-        thread,
-        thread::sleep,
-        time::{Duration, Instant},
     },
 };
 
-pub mod bench;
-pub mod bench_tps_client;
-pub mod blockhash;
-pub mod send_back;
+/// Signal set by the first Ctrl-C, so a run can stop its threads and still defund its
+/// keypairs. A second Ctrl-C exits straight away.
+fn interrupt_signal() -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_interrupted = interrupted.clone();
+    if let Err(err) = ctrlc::set_handler(move || {
+        if handler_interrupted.swap(true, Ordering::Relaxed) {
+            exit(130);
+        }
+        eprintln!("interrupted, stopping the run. Press Ctrl-C again to exit now");
+    }) {
+        eprintln!("Could not set the Ctrl-C handler: {}", err);
+    }
+    interrupted
+}
 
-fn main() {
-    let json_rpc_url = "https://api.devnet.solana.com";
-    let client = Arc::new(RpcClient::new_with_commitment(
-        json_rpc_url.to_string(),
+/// The keypairs of the run `cli_config` describes, extras included, from its keypair store if
+/// it has one
+fn load_run_keypairs(cli_config: &cli::Config) -> Vec<Keypair> {
+    let cli::Config {
+        id,
+        keypair_count,
+        keypair_store,
+        keypair_namespace,
+        ..
+    } = cli_config;
+    let namespace = keypair_namespace.as_deref();
+    match keypair_store.as_deref().filter(|dir| store_exists(dir)) {
+        Some(dir) => load_keypairs(dir, id, *keypair_count, namespace),
+        None => Ok(generate_keypairs(id, *keypair_count as u64, namespace)),
+    }
+    .map(|(keypairs, _extra)| keypairs)
+    .unwrap_or_else(|err| {
+        eprintln!("Error could not load keypairs: {}", err);
+        exit(1);
+    })
+}
+
+/// Start following leader performance if the run asked for it. The monitor reads blocks and
+/// the leader schedule, which only the RPC endpoint serves.
+fn start_leader_monitor(cli_config: &cli::Config) -> Option<LeaderMonitor> {
+    if !cli_config.leader_report {
+        return None;
+    }
+    let rpc_client = Arc::new(RpcClient::new_with_commitment(
+        cli_config.json_rpc_url.clone(),
         CommitmentConfig::confirmed(),
     ));
+    LeaderMonitor::start(rpc_client)
+        .map_err(|err| eprintln!("Error could not start the leader monitor: {}", err))
+        .ok()
+}
 
-    let sender_path = "/Users/harshasomisetty/code/rust_nuke/sender.json";
-    let receiver_path = "/Users/harshasomisetty/code/rust_nuke/receiver.json";
-    let third_path = "/Users/harshasomisetty/code/rust_nuke/third.json";
+/// Slot the cluster is at, if it can be read
+fn current_slot<T: BenchTpsClient>(client: &T) -> Option<u64> {
+    client.get_epoch_info().ok().map(|info| info.absolute_slot)
+}
 
-    let sender_keypair = read_keypair_file(&sender_path).unwrap();
-    let receiver_keypair = read_keypair_file(&receiver_path).unwrap();
-    let third_keypair = read_keypair_file(&third_path).unwrap();
+fn run<T: 'static + BenchTpsClient + Send + Sync>(client: Arc<T>, cli_config: &cli::Config) {
+    let cli::Config {
+        command,
+        id,
+        keypair_count,
+        num_lamports_per_account,
+        report_path,
+        report_format,
+        compute_budget,
+        use_durable_nonce,
+        corpus_path,
+        keypair_store,
+        keypair_namespace,
+        json_rpc_url,
+        slot_range,
+        ..
+    } = cli_config;
 
-    let final_keypair = third_keypair;
-    let final_keypair_balance = client.get_balance(&final_keypair.pubkey()).unwrap_or(0);
-    println!(
-        "final key {} and bal: {}",
-        final_keypair.pubkey(),
-        final_keypair_balance
-    );
+    let id_balance = client.get_balance(&id.pubkey()).unwrap_or(0);
+    println!("funding key {} and bal: {}", id.pubkey(), id_balance);
 
-    let funded_keypairs = generate_and_fund_keypairs(client, &final_keypair, 6, 200000);
+    let mut report = RunReport::new(cli_config);
 
-    let client = Arc::new(RpcClient::new_with_commitment(
-        json_rpc_url.to_string(),
-        CommitmentConfig::confirmed(),
-    ));
-    let return_funds = defund_keypairs(client, &final_keypair, 6);
+    match command {
+        Command::Fund => {
+            if let Err(err) = generate_and_fund_keypairs(
+                client.clone(),
+                id,
+                *keypair_count,
+                *num_lamports_per_account,
+                compute_budget,
+                *use_durable_nonce,
+                keypair_store.as_deref(),
+                keypair_namespace.as_deref(),
+            ) {
+                eprintln!("Error could not fund keys: {:?}", err);
+                exit(1);
+            }
+            let id_balance_after = client.get_balance(&id.pubkey()).unwrap_or(0);
+            report.funding_cost_lamports = id_balance.saturating_sub(id_balance_after);
+        }
+        Command::Defund => {
+            if let Err(err) = defund_keypairs(
+                client,
+                id,
+                *keypair_count,
+                compute_budget,
+                keypair_store.as_deref(),
+                keypair_namespace.as_deref(),
+            ) {
+                eprintln!("Error could not defund keys: {}", err);
+                exit(1);
+            }
+        }
+        Command::Spam => {
+            // Installed before funding, so an interrupt then skips the run but still defunds
+            let interrupted = interrupt_signal();
+            let keypairs = match generate_and_fund_keypairs(
+                client.clone(),
+                id,
+                *keypair_count,
+                *num_lamports_per_account,
+                compute_budget,
+                *use_durable_nonce,
+                keypair_store.as_deref(),
+                keypair_namespace.as_deref(),
+            ) {
+                Ok(keypairs) => keypairs,
+                Err(err) => {
+                    eprintln!("Error could not fund keys: {:?}", err);
+                    exit(1);
+                }
+            };
+            let id_balance_after = client.get_balance(&id.pubkey()).unwrap_or(0);
+            report.funding_cost_lamports = id_balance.saturating_sub(id_balance_after);
+
+            let keypairs_balance = total_balance(&client, &keypairs);
+            report.start_slot = current_slot(client.as_ref());
+            let leader_monitor = start_leader_monitor(cli_config);
+            let stats = do_bench_tps(client.clone(), cli_config, &keypairs, &interrupted);
+            report.end_slot = current_slot(client.as_ref());
+            if let Some(leader_monitor) = leader_monitor {
+                leader_monitor.stop().print();
+            }
+            let keypairs_balance_after = total_balance(&client, &keypairs);
+            report.record_bench(
+                &stats,
+                Some(keypairs_balance.saturating_sub(keypairs_balance_after)),
+            );
+
+            // Still write the report, the run itself succeeded
+            if let Err(err) = defund_keypairs(
+                client,
+                id,
+                *keypair_count,
+                compute_budget,
+                keypair_store.as_deref(),
+                keypair_namespace.as_deref(),
+            ) {
+                eprintln!("Error could not defund keys: {}", err);
+            }
+        }
+        Command::Generate => {
+            let keypairs = match generate_and_fund_keypairs(
+                client.clone(),
+                id,
+                *keypair_count,
+                *num_lamports_per_account,
+                compute_budget,
+                *use_durable_nonce,
+                keypair_store.as_deref(),
+                keypair_namespace.as_deref(),
+            ) {
+                Ok(keypairs) => keypairs,
+                Err(err) => {
+                    eprintln!("Error could not fund keys: {:?}", err);
+                    exit(1);
+                }
+            };
+            let id_balance_after = client.get_balance(&id.pubkey()).unwrap_or(0);
+            report.funding_cost_lamports = id_balance.saturating_sub(id_balance_after);
+
+            // clap requires the corpus path for both generate and replay
+            let corpus_path = corpus_path.as_ref().unwrap();
+            if let Err(err) = generate_corpus(&client, cli_config, &keypairs, corpus_path) {
+                eprintln!("Error could not generate corpus: {:?}", err);
+                exit(1);
+            }
+        }
+        Command::Replay => {
+            let corpus_path = corpus_path.as_ref().unwrap();
+            let interrupted = interrupt_signal();
+            report.start_slot = current_slot(client.as_ref());
+            let leader_monitor = start_leader_monitor(cli_config);
+            match replay_corpus(client.clone(), cli_config, corpus_path, &interrupted) {
+                Ok(stats) => report.record_bench(&stats, None),
+                Err(err) => {
+                    eprintln!("Error could not replay corpus: {:?}", err);
+                    exit(1);
+                }
+            }
+            report.end_slot = current_slot(client.as_ref());
+            if let Some(leader_monitor) = leader_monitor {
+                leader_monitor.stop().print();
+            }
+        }
+        Command::Balances => {
+            let keypairs = load_run_keypairs(cli_config);
+            for (i, keypair) in keypairs.iter().take(*keypair_count).enumerate() {
+                let balance = client.get_balance(&keypair.pubkey()).unwrap_or(0);
+                println!(
+                    "account {}: address: {}, balance: {}",
+                    i,
+                    keypair.pubkey(),
+                    balance
+                );
+            }
+        }
+        Command::SaveKeypairs => {
+            // extract_args requires the store for save-keypairs and list-keypairs
+            let dir = keypair_store.as_ref().unwrap();
+            if store_exists(dir) {
+                eprintln!("Error {:?} already holds a keypair store", dir);
+                exit(1);
+            }
+            if let Err(err) = load_or_generate_keypairs(
+                id,
+                *keypair_count,
+                keypair_namespace.as_deref(),
+                Some(dir),
+            ) {
+                eprintln!("Error could not save keypairs: {}", err);
+                exit(1);
+            }
+        }
+        Command::ListKeypairs => {
+            let dir = keypair_store.as_ref().unwrap();
+            let (manifest, keypairs) = match read_manifest(dir)
+                .and_then(|manifest| read_keypairs(dir).map(|keypairs| (manifest, keypairs)))
+            {
+                Ok(store) => store,
+                Err(err) => {
+                    eprintln!("Error could not read keypair store {:?}: {}", dir, err);
+                    exit(1);
+                }
+            };
+            if let Some(namespace) = &manifest.namespace {
+                println!("namespace: {}", namespace);
+            }
+            println!(
+                "seed pubkey: {}, keypairs: {} + {} extra, lamports per account: {}, \
+                 durable nonce: {}",
+                manifest.seed_pubkey,
+                manifest.keypair_count,
+                manifest.extra,
+                manifest.lamports_per_account,
+                manifest.durable_nonce
+            );
+            for (i, keypair) in keypairs.iter().enumerate() {
+                println!("account {}: address: {}", i, keypair.pubkey());
+            }
+        }
+        Command::Stats => {
+            // extract_args requires a slot range and a report path for stats
+            let (start_slot, end_slot) = slot_range.unwrap();
+            let report_path = report_path.as_ref().unwrap();
+            // The funding keypair pays for funding transactions, so they count as ours too
+            let our_keys: HashSet<Pubkey> = load_run_keypairs(cli_config)
+                .iter()
+                .chain([id])
+                .map(|keypair| keypair.pubkey())
+                .collect();
+            let rpc_client =
+                RpcClient::new_with_commitment(json_rpc_url.clone(), CommitmentConfig::confirmed());
+            let stats = collect_block_stats(&rpc_client, start_slot, end_slot, &our_keys)
+                .unwrap_or_else(|err| {
+                    eprintln!("Error could not collect block stats: {}", err);
+                    exit(1);
+                });
+            stats.print();
+            if let Err(err) = stats.write(report_path, *report_format) {
+                eprintln!(
+                    "Error could not write block stats {:?}: {}",
+                    report_path, err
+                );
+                exit(1);
+            }
+            println!("block stats written to {:?}", report_path);
+            // The stats are this command's report
+            return;
+        }
+    }
+
+    if let Some(report_path) = report_path {
+        if let Err(err) = report.write(report_path, *report_format) {
+            eprintln!("Error could not write report {:?}: {}", report_path, err);
+            exit(1);
+        }
+        println!("report written to {:?}", report_path);
+    }
+}
+
+fn main() {
+    solana_logger::setup_with_default("solana=info");
+
+    let matches = cli::build_args(solana_version::version!()).get_matches();
+    let cli_config = cli::extract_args(&matches);
+
+    let cli::Config {
+        json_rpc_url,
+        websocket_url,
+        commitment_config,
+        external_client_type,
+        use_quic,
+        tpu_fanout_slots,
+        rpc_tpu_sockets,
+        ..
+    } = &cli_config;
+
+    match external_client_type {
+        ExternalClientType::RpcClient => {
+            let client = Arc::new(RpcClient::new_with_commitment(
+                json_rpc_url.to_string(),
+                *commitment_config,
+            ));
+            run(client, &cli_config);
+        }
+        ExternalClientType::TpuClient => {
+            let rpc_client = Arc::new(RpcClient::new_with_commitment(
+                json_rpc_url.to_string(),
+                *commitment_config,
+            ));
+            if *use_quic {
+                connection_cache::set_use_quic(true);
+            }
+            let client = Arc::new(
+                TpuClient::new(
+                    rpc_client,
+                    websocket_url,
+                    TpuClientConfig {
+                        fanout_slots: *tpu_fanout_slots,
+                    },
+                )
+                .unwrap_or_else(|err| {
+                    eprintln!("Could not create TpuClient {:?}", err);
+                    exit(1);
+                }),
+            );
+            run(client, &cli_config);
+        }
+        ExternalClientType::ThinClient => {
+            if *use_quic {
+                connection_cache::set_use_quic(true);
+            }
+            // extract_args guarantees both sockets are present for the ThinClient
+            let client = Arc::new(create_client(rpc_tpu_sockets.unwrap()));
+            run(client, &cli_config);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,226 @@
+use {
+    crate::{bench_tps_client::*, blockhash::get_latest_blockhash, priority_fee::ComputeBudget},
+    log::*,
+    rayon::prelude::*,
+    solana_client::nonce_utils::data_from_account,
+    solana_sdk::{
+        hash::{hashv, Hash},
+        instruction::Instruction,
+        message::Message,
+        nonce::State,
+        pubkey::Pubkey,
+        signature::{keypair_from_seed, Keypair, Signer},
+        system_instruction,
+        transaction::Transaction,
+    },
+    std::{
+        collections::HashMap,
+        sync::Arc,
+        thread::sleep,
+        time::{Duration, Instant},
+    },
+};
+
+// getMultipleAccounts rejects requests for more accounts than this
+pub(crate) const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+// How long to wait for a batch of nonce accounts to show up before resending it
+const NONCE_CREATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const NONCE_CREATE_RETRIES: usize = 5;
+
+// How long to wait for nonce accounts to be readable before deciding they were never created
+const NONCE_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Nonce account and the nonce currently stored in it, keyed by the nonce authority
+pub type DurableNonces = HashMap<Pubkey, (Pubkey, Hash)>;
+
+/// A durable nonce account as read from the cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceAccount {
+    pub lamports: u64,
+    /// The stored nonce, which transactions use in place of a recent blockhash
+    pub blockhash: Hash,
+}
+
+/// The nonce account of `authority`. It's derived from the authority's secret so later runs
+/// and the defund path find the same account without having to store it anywhere.
+pub fn nonce_keypair(authority: &Keypair) -> Keypair {
+    let seed = hashv(&[b"nonce", &authority.to_bytes()[..32]]);
+    keypair_from_seed(seed.as_ref()).unwrap()
+}
+
+/// Nonce account of each of `authorities`, keyed by the authority. Deriving a nonce address
+/// is about as expensive as signing, so callers look them up here instead of rederiving them.
+pub fn nonce_accounts(authorities: &[&Keypair]) -> HashMap<Pubkey, Pubkey> {
+    authorities
+        .par_iter()
+        .map(|authority| (authority.pubkey(), nonce_keypair(authority).pubkey()))
+        .collect()
+}
+
+/// Rent-exempt balance of a nonce account
+pub fn nonce_rent<T: BenchTpsClient>(client: &T) -> Result<u64> {
+    client.get_minimum_balance_for_rent_exemption(State::size())
+}
+
+/// Read a batch of nonce accounts, `None` for accounts that don't exist or don't hold an
+/// initialized nonce
+pub fn get_nonce_accounts<T: BenchTpsClient>(
+    client: &T,
+    nonce_pubkeys: &[Pubkey],
+) -> Result<Vec<Option<NonceAccount>>> {
+    let mut nonce_accounts = Vec::with_capacity(nonce_pubkeys.len());
+    for chunk in nonce_pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        for account in client.get_multiple_accounts(chunk)? {
+            nonce_accounts.push(account.and_then(|account| {
+                let data = data_from_account(&account).ok()?;
+                Some(NonceAccount {
+                    lamports: account.lamports,
+                    blockhash: data.blockhash,
+                })
+            }));
+        }
+    }
+    Ok(nonce_accounts)
+}
+
+/// The stored nonce of every account, waiting for any that aren't visible yet, such as
+/// accounts created a moment ago that haven't reached the client's commitment. Fails with
+/// `NonceAccountsMissing` if some still aren't after `NONCE_WAIT_TIMEOUT`.
+pub fn wait_for_nonce_hashes<T: BenchTpsClient>(
+    client: &T,
+    nonce_pubkeys: &[Pubkey],
+) -> Result<Vec<Hash>> {
+    let start = Instant::now();
+    loop {
+        let result = get_nonce_accounts(client, nonce_pubkeys).and_then(|nonce_accounts| {
+            let missing = nonce_accounts
+                .iter()
+                .filter(|account| account.is_none())
+                .count();
+            if missing == 0 {
+                Ok(nonce_accounts
+                    .into_iter()
+                    .map(|account| account.unwrap().blockhash)
+                    .collect())
+            } else {
+                Err(BenchTpsError::NonceAccountsMissing { missing })
+            }
+        });
+        match result {
+            Ok(hashes) => return Ok(hashes),
+            Err(err) if start.elapsed() >= NONCE_WAIT_TIMEOUT => {
+                error!("Gave up waiting for nonce accounts: {}", err);
+                return Err(err);
+            }
+            Err(BenchTpsError::NonceAccountsMissing { missing }) => {
+                info!("Waiting for {} nonce accounts...", missing)
+            }
+            Err(err) => info!("Couldn't get nonce accounts: {:?}", err),
+        }
+        sleep(Duration::from_secs(1));
+    }
+}
+
+/// The current nonce of each of `senders`, whose nonce accounts are in `nonce_accounts`
+pub fn get_durable_nonces<T: BenchTpsClient>(
+    client: &T,
+    senders: &[&Keypair],
+    nonce_accounts: &HashMap<Pubkey, Pubkey>,
+) -> Result<DurableNonces> {
+    let nonce_pubkeys: Vec<Pubkey> = senders
+        .iter()
+        .map(|sender| nonce_accounts[&sender.pubkey()])
+        .collect();
+    let hashes = wait_for_nonce_hashes(client, &nonce_pubkeys)?;
+    Ok(senders
+        .iter()
+        .zip(nonce_pubkeys.into_iter().zip(hashes))
+        .map(|(sender, nonce)| (sender.pubkey(), nonce))
+        .collect())
+}
+
+/// Build a message whose first instruction advances `nonce_account`, which `payer` is the
+/// authority of, so it can be signed with the stored nonce instead of a recent blockhash
+pub fn nonced_message(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    nonce_account: &Pubkey,
+) -> Message {
+    Message::new_with_nonce(instructions.to_vec(), Some(payer), nonce_account, payer)
+}
+
+/// Create the nonce account of each of `authorities` that doesn't have one yet. Each authority
+/// pays for its own account.
+pub fn create_nonce_accounts<T: 'static + BenchTpsClient + Send + Sync>(
+    client: &Arc<T>,
+    authorities: &[&Keypair],
+    compute_budget: &ComputeBudget,
+) -> Result<()> {
+    let rent = nonce_rent(client.as_ref())?;
+    let nonce_keypairs: Vec<Keypair> = authorities
+        .par_iter()
+        .map(|authority| nonce_keypair(authority))
+        .collect();
+    let nonce_pubkeys: Vec<Pubkey> = nonce_keypairs.iter().map(|k| k.pubkey()).collect();
+
+    let mut missing: Vec<usize> = get_nonce_accounts(client.as_ref(), &nonce_pubkeys)?
+        .iter()
+        .enumerate()
+        .filter_map(|(i, account)| account.is_none().then(|| i))
+        .collect();
+
+    let mut tries = 0;
+    while !missing.is_empty() {
+        if tries == NONCE_CREATE_RETRIES {
+            return Err(BenchTpsError::Custom(format!(
+                "failed to create {} nonce accounts",
+                missing.len()
+            )));
+        }
+        println!(
+            "{} {} nonce accounts",
+            if tries == 0 { "creating" } else { "retrying" },
+            missing.len()
+        );
+
+        let blockhash = get_latest_blockhash(client.as_ref());
+        let budget_instructions = compute_budget.initial_instructions();
+        let transactions: Vec<Transaction> = missing
+            .par_iter()
+            .map(|i| {
+                let authority = authorities[*i];
+                let nonce = &nonce_keypairs[*i];
+                let mut instructions = budget_instructions.clone();
+                instructions.extend(system_instruction::create_nonce_account(
+                    &authority.pubkey(),
+                    &nonce.pubkey(),
+                    &authority.pubkey(),
+                    rent,
+                ));
+                let message = Message::new(&instructions, Some(&authority.pubkey()));
+                Transaction::new(&[authority, nonce], message, blockhash)
+            })
+            .collect();
+        client.send_batch(transactions)?;
+
+        let start = Instant::now();
+        while !missing.is_empty() && start.elapsed() < NONCE_CREATE_TIMEOUT {
+            sleep(Duration::from_secs(1));
+            let pubkeys: Vec<Pubkey> = missing.iter().map(|i| nonce_pubkeys[*i]).collect();
+            match get_nonce_accounts(client.as_ref(), &pubkeys) {
+                Ok(accounts) => {
+                    missing = missing
+                        .into_iter()
+                        .zip(accounts)
+                        .filter_map(|(i, account)| account.is_none().then(|| i))
+                        .collect();
+                }
+                Err(err) => info!("Couldn't get nonce accounts: {:?}", err),
+            }
+        }
+        tries += 1;
+    }
+    Ok(())
+}
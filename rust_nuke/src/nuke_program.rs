@@ -0,0 +1,97 @@
+use solana_sdk::{
+    hash::hashv,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+
+solana_sdk::declare_id!("HzwyTmrungBwbSmaBPPRo97iTC8Grqv7AQw297BGFsF2");
+
+/// Anchor's 8-byte instruction discriminator: the first bytes of `sha256("global:<name>")`
+pub fn instruction_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hashv(&[b"global:", name.as_bytes()]).to_bytes()[..8]);
+    discriminator
+}
+
+/// Arguments of the program's `spam` instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpamArgs {
+    /// Mixed into the PDA seeds the program derives
+    pub random: u32,
+    /// Number of PDAs the program derives, which is what makes the instruction compute heavy
+    pub loop_counter: u16,
+    /// Lamports transferred from `bad_actor` to `receiver`
+    pub amount: u64,
+}
+
+impl SpamArgs {
+    /// Anchor instruction data: the discriminator followed by the Borsh encoded args
+    pub fn data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(8 + 4 + 2 + 8);
+        data.extend_from_slice(&instruction_discriminator("spam"));
+        data.extend_from_slice(&self.random.to_le_bytes());
+        data.extend_from_slice(&self.loop_counter.to_le_bytes());
+        data.extend_from_slice(&self.amount.to_le_bytes());
+        data
+    }
+}
+
+/// Build a `spam` instruction. `bad_actor` pays for the inner transfer, so it has to sign as
+/// well as `signer`; the two are usually the same key.
+pub fn spam(
+    program_id: &Pubkey,
+    bad_actor: &Pubkey,
+    receiver: &Pubkey,
+    signer: &Pubkey,
+    args: SpamArgs,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &args.data(),
+        vec![
+            AccountMeta::new(*bad_actor, true),
+            AccountMeta::new(*receiver, false),
+            AccountMeta::new_readonly(*signer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spam_instruction_layout() {
+        // sha256("global:spam")[..8], as Anchor computes it
+        let discriminator = [232, 233, 103, 7, 101, 221, 29, 72];
+        assert_eq!(instruction_discriminator("spam"), discriminator);
+
+        let args = SpamArgs {
+            random: 0x0102_0304,
+            loop_counter: 0x0506,
+            amount: 0x0708_090a_0b0c_0d0e,
+        };
+        let mut expected = discriminator.to_vec();
+        expected.extend_from_slice(&[4, 3, 2, 1]);
+        expected.extend_from_slice(&[6, 5]);
+        expected.extend_from_slice(&[14, 13, 12, 11, 10, 9, 8, 7]);
+        assert_eq!(args.data(), expected);
+
+        let bad_actor = Pubkey::new_unique();
+        let receiver = Pubkey::new_unique();
+        let instruction = spam(&id(), &bad_actor, &receiver, &bad_actor, args);
+        assert_eq!(instruction.program_id, id());
+        assert_eq!(instruction.data, expected);
+        assert_eq!(
+            instruction.accounts,
+            vec![
+                AccountMeta::new(bad_actor, true),
+                AccountMeta::new(receiver, false),
+                AccountMeta::new_readonly(bad_actor, true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use {
+    crate::bench_tps_client::BenchTpsClient,
+    log::*,
+    solana_sdk::timing::duration_as_s,
+    std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, RwLock,
+        },
+        thread::sleep,
+        time::{Duration, Instant},
+    },
+};
+
+/// Transaction count observed over one sampling interval
+#[derive(Debug, Clone, Default)]
+pub struct TpsSample {
+    /// Time since the sampler started, at the end of the interval
+    pub elapsed: Duration,
+    /// Transactions processed by the cluster during the interval
+    pub txs: u64,
+    pub tps: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SampleStats {
+    /// Maximum TPS reported by this node
+    pub tps: f32,
+    /// Total time taken for those txs
+    pub elapsed: Duration,
+    /// Total transactions reported by this node
+    pub txs: u64,
+    /// Every interval observed, in order
+    pub samples: Vec<TpsSample>,
+}
+
+impl SampleStats {
+    /// Average TPS over the whole sampling window
+    pub fn overall_tps(&self) -> f32 {
+        let elapsed = duration_as_s(&self.elapsed);
+        if elapsed > 0.0 {
+            self.txs as f32 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+pub fn sample_txs<T>(
+    exit_signal: &Arc<AtomicBool>,
+    sample_stats: &Arc<RwLock<Vec<(String, SampleStats)>>>,
+    sample_period: u64,
+    client: &Arc<T>,
+) where
+    T: BenchTpsClient,
+{
+    let mut max_tps = 0.0;
+    let mut samples = vec![];
+    let mut now = Instant::now();
+    let start_time = now;
+    let initial_txs = loop {
+        match client.get_transaction_count() {
+            Ok(tx_count) => break tx_count,
+            Err(err) => {
+                info!("Couldn't get initial transaction count {:?}", err);
+                sleep(Duration::from_secs(1));
+            }
+        }
+    };
+    let mut last_txs = initial_txs;
+
+    loop {
+        // Wait out the interval first so the first sample covers a full period
+        sleep(Duration::from_secs(sample_period));
+
+        let total_elapsed = start_time.elapsed();
+        let elapsed = now.elapsed();
+        now = Instant::now();
+        let mut txs = match client.get_transaction_count() {
+            Err(e) => {
+                info!("Couldn't get transaction count {:?}", e);
+                if exit_signal.load(Ordering::Relaxed) {
+                    let total_txs = last_txs - initial_txs;
+                    txs_done(
+                        sample_stats,
+                        client,
+                        max_tps,
+                        total_elapsed,
+                        total_txs,
+                        samples,
+                    );
+                    return;
+                }
+                continue;
+            }
+            Ok(tx_count) => tx_count,
+        };
+
+        if txs < last_txs {
+            info!("Expected txs({}) >= last_txs({})", txs, last_txs);
+            txs = last_txs;
+        }
+        let total_txs = txs - initial_txs;
+        let sample_txs = txs - last_txs;
+        last_txs = txs;
+
+        let tps = sample_txs as f32 / duration_as_s(&elapsed);
+        if tps > max_tps {
+            max_tps = tps;
+        }
+        samples.push(TpsSample {
+            elapsed: total_elapsed,
+            txs: sample_txs,
+            tps,
+        });
+
+        info!(
+            "Sampler {:9.2} TPS, Transactions: {:6}, Total transactions: {} over {} s",
+            tps,
+            sample_txs,
+            total_txs,
+            total_elapsed.as_secs(),
+        );
+
+        if exit_signal.load(Ordering::Relaxed) {
+            txs_done(
+                sample_stats,
+                client,
+                max_tps,
+                total_elapsed,
+                total_txs,
+                samples,
+            );
+            return;
+        }
+    }
+}
+
+fn txs_done<T: BenchTpsClient>(
+    sample_stats: &Arc<RwLock<Vec<(String, SampleStats)>>>,
+    client: &Arc<T>,
+    max_tps: f32,
+    elapsed: Duration,
+    txs: u64,
+    samples: Vec<TpsSample>,
+) {
+    let stats = SampleStats {
+        tps: max_tps,
+        elapsed,
+        txs,
+        samples,
+    };
+    sample_stats.write().unwrap().push((client.addr(), stats));
+}
+
+/// Print the per-interval samples and a per-node summary of everything the sampler saw
+pub fn compute_and_report_stats(
+    maxes: &Arc<RwLock<Vec<(String, SampleStats)>>>,
+    sample_period: u64,
+    tx_send_elapsed: &Duration,
+    total_tx_send_count: usize,
+) {
+    let maxes = maxes.read().unwrap();
+
+    for (addr, stats) in maxes.iter() {
+        println!("\nTPS samples for {} ({}s interval)", addr, sample_period);
+        println!("   Elapsed (s) |           TPS | Transactions");
+        println!("---------------+---------------+--------------");
+        for sample in &stats.samples {
+            println!(
+                "{:14.2} | {:13.2} | {}",
+                duration_as_s(&sample.elapsed),
+                sample.tps,
+                sample.txs
+            );
+        }
+    }
+
+    let mut max_of_maxes = 0.0;
+    let mut max_tx_count = 0;
+    println!("\n Node address        |       Max TPS |   Overall TPS | Total Transactions");
+    println!("---------------------+---------------+---------------+--------------------");
+    for (addr, stats) in maxes.iter() {
+        let maybe_flag = match stats.txs {
+            0 => "!!!!!",
+            _ => "",
+        };
+        println!(
+            "{:20} | {:13.2} | {:13.2} | {} {}",
+            addr,
+            stats.tps,
+            stats.overall_tps(),
+            stats.txs,
+            maybe_flag
+        );
+        if stats.tps > max_of_maxes {
+            max_of_maxes = stats.tps;
+        }
+        if stats.txs > max_tx_count {
+            max_tx_count = stats.txs;
+        }
+    }
+    println!(
+        "\nHighest TPS: {:.2} sampling period {}s max transactions: {}",
+        max_of_maxes, sample_period, max_tx_count
+    );
+    println!(
+        "Sent {} transactions in {:.2}s, {:.2} TPS from the client side",
+        total_tx_send_count,
+        duration_as_s(tx_send_elapsed),
+        total_tx_send_count as f32 / duration_as_s(tx_send_elapsed),
+    );
+}
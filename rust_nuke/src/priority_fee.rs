@@ -0,0 +1,209 @@
+use {
+    solana_sdk::{
+        borsh::try_from_slice_unchecked,
+        compute_budget::{self, ComputeBudgetInstruction},
+        instruction::Instruction,
+        transaction::Transaction,
+    },
+    std::{fmt, str::FromStr},
+};
+
+/// How the compute unit price, in micro-lamports, is chosen over a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputeUnitPrice {
+    Fixed(u64),
+    /// Ramp from `start` to `end` in `steps` evenly spaced tiers over the course of the run
+    Sweep {
+        start: u64,
+        end: u64,
+        steps: u64,
+    },
+}
+
+impl ComputeUnitPrice {
+    /// Price for a transaction generated `progress` (0.0 to 1.0) of the way through a run
+    pub fn price_at(&self, progress: f64) -> u64 {
+        match *self {
+            Self::Fixed(price) => price,
+            Self::Sweep { start, end, steps } => {
+                if steps <= 1 {
+                    return start;
+                }
+                let tier = ((progress.clamp(0.0, 1.0) * steps as f64) as u64).min(steps - 1);
+                let delta = (end as i128 - start as i128) * tier as i128 / (steps - 1) as i128;
+                (start as i128 + delta) as u64
+            }
+        }
+    }
+
+    pub fn is_sweep(&self) -> bool {
+        matches!(self, Self::Sweep { .. })
+    }
+}
+
+impl FromStr for ComputeUnitPrice {
+    type Err = String;
+
+    /// Parse `START-END:STEPS` sweeps; a plain number is a fixed price
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|err| format!("invalid compute unit price {:?}: {}", value, err))
+        };
+        match s.split_once('-') {
+            None => Ok(Self::Fixed(parse(s)?)),
+            Some((start, rest)) => {
+                let (end, steps) = rest.split_once(':').unwrap_or((rest, "10"));
+                let steps = parse(steps)?;
+                if steps == 0 {
+                    return Err("a sweep needs at least one step".to_string());
+                }
+                Ok(Self::Sweep {
+                    start: parse(start)?,
+                    end: parse(end)?,
+                    steps,
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for ComputeUnitPrice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fixed(price) => write!(f, "{}", price),
+            Self::Sweep { start, end, steps } => write!(f, "{}-{}:{}", start, end, steps),
+        }
+    }
+}
+
+/// ComputeBudget instructions attached to every generated transaction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComputeBudget {
+    pub compute_unit_limit: Option<u32>,
+    pub compute_unit_price: Option<ComputeUnitPrice>,
+}
+
+impl ComputeBudget {
+    /// Compute unit price `progress` of the way through a run, if one is set
+    pub fn price_at(&self, progress: f64) -> Option<u64> {
+        self.compute_unit_price
+            .map(|price| price.price_at(progress))
+    }
+
+    /// Instructions to prepend to a transaction paying `compute_unit_price`
+    pub fn instructions(&self, compute_unit_price: Option<u64>) -> Vec<Instruction> {
+        let mut instructions = vec![];
+        if let Some(limit) = self.compute_unit_limit {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(limit));
+        }
+        if let Some(price) = compute_unit_price {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+        }
+        instructions
+    }
+
+    /// Instructions for transactions outside the spam loop, such as funding and defunding,
+    /// which pay the price the run starts at
+    pub fn initial_instructions(&self) -> Vec<Instruction> {
+        self.instructions(self.price_at(0.0))
+    }
+}
+
+/// The compute unit price `transaction` pays, 0 if it doesn't set one
+pub fn compute_unit_price_of(transaction: &Transaction) -> u64 {
+    let message = &transaction.message;
+    message
+        .instructions
+        .iter()
+        .filter(|ix| message.account_keys[ix.program_id_index as usize] == compute_budget::id())
+        .find_map(
+            |ix| match try_from_slice_unchecked::<ComputeBudgetInstruction>(&ix.data) {
+                Ok(ComputeBudgetInstruction::SetComputeUnitPrice(price)) => Some(price),
+                _ => None,
+            },
+        )
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            hash::Hash,
+            message::Message,
+            signature::{Keypair, Signer},
+            system_instruction,
+        },
+    };
+
+    #[test]
+    fn test_price_sweep_tiers() {
+        let sweep: ComputeUnitPrice = "100-1000".parse().unwrap();
+        assert_eq!(
+            sweep,
+            ComputeUnitPrice::Sweep {
+                start: 100,
+                end: 1000,
+                steps: 10,
+            }
+        );
+        assert_eq!(sweep.price_at(0.0), 100);
+        // Each tier covers a tenth of the run, and the last one ends on `end`
+        assert_eq!(sweep.price_at(0.0999), 100);
+        assert_eq!(sweep.price_at(0.1), 200);
+        assert_eq!(sweep.price_at(0.5), 600);
+        assert_eq!(sweep.price_at(0.9), 1000);
+        assert_eq!(sweep.price_at(1.0), 1000);
+        assert_eq!(sweep.price_at(1.5), 1000);
+        assert_eq!(sweep.price_at(-1.0), 100);
+
+        let descending: ComputeUnitPrice = "1000-100:4".parse().unwrap();
+        assert_eq!(descending.price_at(0.25), 700);
+        assert_eq!(descending.price_at(1.0), 100);
+
+        let one_step: ComputeUnitPrice = "5-50:1".parse().unwrap();
+        assert_eq!(one_step.price_at(1.0), 5);
+        assert_eq!("7".parse(), Ok(ComputeUnitPrice::Fixed(7)));
+        assert!("5-50:0".parse::<ComputeUnitPrice>().is_err());
+        assert!("5-x".parse::<ComputeUnitPrice>().is_err());
+        assert_eq!(descending.to_string(), "1000-100:4");
+    }
+
+    #[test]
+    fn test_compute_budget_instructions() {
+        assert!(ComputeBudget::default().initial_instructions().is_empty());
+
+        let compute_budget = ComputeBudget {
+            compute_unit_limit: Some(200_000),
+            compute_unit_price: Some("10-20:2".parse().unwrap()),
+        };
+        let instructions = compute_budget.instructions(compute_budget.price_at(1.0));
+        assert_eq!(
+            instructions,
+            vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+                ComputeBudgetInstruction::set_compute_unit_price(20),
+            ]
+        );
+        assert!(instructions
+            .iter()
+            .all(|instruction| instruction.program_id == compute_budget::id()));
+
+        let payer = Keypair::new();
+        let pays_price = |mut instructions: Vec<Instruction>| {
+            instructions.push(system_instruction::transfer(
+                &payer.pubkey(),
+                &payer.pubkey(),
+                1,
+            ));
+            let message = Message::new(&instructions, Some(&payer.pubkey()));
+            compute_unit_price_of(&Transaction::new(&[&payer], message, Hash::default()))
+        };
+        assert_eq!(pays_price(compute_budget.initial_instructions()), 10);
+        assert_eq!(pays_price(vec![]), 0);
+    }
+}
@@ -0,0 +1,310 @@
+use {
+    crate::leader::LeaderTracker,
+    log::*,
+    serde::Serialize,
+    serde_json::{json, Value},
+    solana_sdk::{
+        packet::PACKET_DATA_SIZE, sanitize::Sanitize, signature::Signature,
+        transaction::Transaction,
+    },
+    std::{
+        collections::{BTreeMap, HashMap},
+        io::{self, Read},
+        net::{IpAddr, SocketAddr, UdpSocket},
+        sync::Mutex,
+    },
+    tiny_http::{Header, Method, Request, Response, Server},
+};
+
+// Comfortably more than a base64 transaction of PACKET_DATA_SIZE and its JSON-RPC envelope
+const MAX_REQUEST_LEN: u64 = 16 * 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SEND_FAILED: i64 = -32000;
+
+/// Where the proxy forwards transactions to
+pub trait TpuTargets: Send + Sync {
+    fn tpu_sockets(&self) -> Vec<SocketAddr>;
+}
+
+/// Fixed TPUs, e.g. a single local validator
+impl TpuTargets for Vec<SocketAddr> {
+    fn tpu_sockets(&self) -> Vec<SocketAddr> {
+        self.clone()
+    }
+}
+
+/// The TPUs of the next `fanout` leaders
+pub struct UpcomingLeaders {
+    pub tracker: LeaderTracker,
+    pub fanout: usize,
+}
+
+impl TpuTargets for UpcomingLeaders {
+    fn tpu_sockets(&self) -> Vec<SocketAddr> {
+        self.tracker.leader_tpu_sockets(self.fanout)
+    }
+}
+
+/// What one client submitted through the proxy
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct SubmitterStats {
+    /// `sendTransaction` requests
+    pub received: u64,
+    /// Transactions sent to at least one TPU
+    pub forwarded: u64,
+    /// Transactions that couldn't be decoded or verified, or reached no TPU
+    pub rejected: u64,
+    /// UDP packets sent, one per TPU a transaction was forwarded to
+    pub packets: u64,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Accepts `sendTransaction` JSON-RPC requests and forwards the transactions to TPUs over
+/// UDP, for clients that can't send UDP themselves
+pub struct TpuProxy<T: TpuTargets> {
+    targets: T,
+    socket: UdpSocket,
+    stats: Mutex<HashMap<IpAddr, SubmitterStats>>,
+}
+
+impl<T: TpuTargets> TpuProxy<T> {
+    pub fn new(targets: T) -> io::Result<Self> {
+        Ok(Self {
+            targets,
+            socket: UdpSocket::bind("0.0.0.0:0")?,
+            stats: Mutex::default(),
+        })
+    }
+
+    pub fn submitter_stats(&self) -> HashMap<IpAddr, SubmitterStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Answer HTTP requests from `server` until it shuts down. Several threads can serve the
+    /// same server.
+    pub fn serve(&self, server: &Server) {
+        for request in server.incoming_requests() {
+            self.respond(request);
+        }
+    }
+
+    fn respond(&self, mut request: Request) {
+        let response = if *request.method() != Method::Post {
+            Response::from_string("expected a JSON-RPC POST request").with_status_code(405)
+        } else {
+            let submitter = request
+                .remote_addr()
+                .map_or(IpAddr::from([0, 0, 0, 0]), SocketAddr::ip);
+            let mut body = String::new();
+            let reply = match request
+                .as_reader()
+                .take(MAX_REQUEST_LEN)
+                .read_to_string(&mut body)
+            {
+                Ok(_) => self.handle_request(submitter, &body),
+                Err(err) => error_reply(&Value::Null, RpcError::new(PARSE_ERROR, err.to_string())),
+            };
+            Response::from_string(reply.to_string()).with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+            )
+        };
+        if let Err(err) = request.respond(response) {
+            debug!("Couldn't respond to a request: {}", err);
+        }
+    }
+
+    /// Answer one JSON-RPC request from `submitter`
+    pub fn handle_request(&self, submitter: IpAddr, body: &str) -> Value {
+        let request: Value = match serde_json::from_str(body) {
+            Ok(request) => request,
+            Err(err) => {
+                return error_reply(&Value::Null, RpcError::new(PARSE_ERROR, err.to_string()))
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        match request.get("method").and_then(Value::as_str) {
+            Some("sendTransaction") => match self.send_transaction(submitter, &params) {
+                Ok(signature) => {
+                    json!({"jsonrpc": "2.0", "result": signature.to_string(), "id": id})
+                }
+                Err(err) => error_reply(&id, err),
+            },
+            Some("getSubmitterStats") => {
+                let stats: BTreeMap<_, _> = self
+                    .submitter_stats()
+                    .into_iter()
+                    .map(|(submitter, stats)| (submitter.to_string(), stats))
+                    .collect();
+                json!({"jsonrpc": "2.0", "result": stats, "id": id})
+            }
+            Some(method) => error_reply(
+                &id,
+                RpcError::new(METHOD_NOT_FOUND, format!("method {} isn't proxied", method)),
+            ),
+            None => error_reply(&id, RpcError::new(INVALID_REQUEST, "missing method")),
+        }
+    }
+
+    fn send_transaction(&self, submitter: IpAddr, params: &Value) -> Result<Signature, RpcError> {
+        let result = decode_transaction(params).and_then(|(wire_transaction, transaction)| {
+            match self.forward(&wire_transaction) {
+                0 => Err(RpcError::new(SEND_FAILED, "no leader TPU could be reached")),
+                packets => Ok((transaction.signatures[0], packets)),
+            }
+        });
+
+        let mut stats = self.stats.lock().unwrap();
+        let stats = stats.entry(submitter).or_default();
+        stats.received += 1;
+        match &result {
+            Ok((_, packets)) => {
+                stats.forwarded += 1;
+                stats.packets += *packets as u64;
+            }
+            Err(_) => stats.rejected += 1,
+        }
+        result.map(|(signature, _)| signature)
+    }
+
+    /// Send `wire_transaction` to every target TPU, returning how many it was sent to
+    pub fn forward(&self, wire_transaction: &[u8]) -> usize {
+        self.targets
+            .tpu_sockets()
+            .iter()
+            .filter(|tpu| match self.socket.send_to(wire_transaction, tpu) {
+                Ok(_) => true,
+                Err(err) => {
+                    debug!("Couldn't forward a transaction to {}: {}", tpu, err);
+                    false
+                }
+            })
+            .count()
+    }
+}
+
+fn error_reply(id: &Value, err: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": {"code": err.code, "message": err.message},
+        "id": id,
+    })
+}
+
+/// The wire bytes of the transaction in `sendTransaction` params, and the transaction they
+/// hold once its signatures check out
+fn decode_transaction(params: &Value) -> Result<(Vec<u8>, Transaction), RpcError> {
+    let invalid = |message: String| RpcError::new(INVALID_PARAMS, message);
+    let encoded = params
+        .get(0)
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("expected an encoded transaction".to_string()))?;
+    // Like the RPC service, base58 unless the config says otherwise
+    let encoding = params
+        .get(1)
+        .and_then(|config| config.get("encoding"))
+        .and_then(Value::as_str)
+        .unwrap_or("base58");
+    let wire_transaction = match encoding {
+        "base58" => bs58::decode(encoded)
+            .into_vec()
+            .map_err(|err| invalid(format!("invalid base58: {}", err)))?,
+        "base64" => {
+            base64::decode(encoded).map_err(|err| invalid(format!("invalid base64: {}", err)))?
+        }
+        encoding => return Err(invalid(format!("unsupported encoding {}", encoding))),
+    };
+    if wire_transaction.len() > PACKET_DATA_SIZE {
+        return Err(invalid(format!(
+            "transaction is {} bytes, at most {} fit in a packet",
+            wire_transaction.len(),
+            PACKET_DATA_SIZE
+        )));
+    }
+    let transaction: Transaction = bincode::deserialize(&wire_transaction)
+        .map_err(|err| invalid(format!("invalid transaction: {}", err)))?;
+    transaction
+        .sanitize()
+        .map_err(|err| invalid(format!("invalid transaction: {}", err)))?;
+    transaction
+        .verify()
+        .map_err(|err| invalid(format!("invalid signature: {}", err)))?;
+    Ok((wire_transaction, transaction))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{hash::Hash, signature::Keypair, signer::Signer, system_transaction},
+        std::time::Duration,
+    };
+
+    #[test]
+    fn test_tpu_proxy_forwards_transactions() {
+        // Stands in for a validator's TPU
+        let tpu = UdpSocket::bind("127.0.0.1:0").unwrap();
+        tpu.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let proxy = TpuProxy::new(vec![tpu.local_addr().unwrap()]).unwrap();
+
+        let from = Keypair::new();
+        let transaction =
+            system_transaction::transfer(&from, &from.pubkey(), 1, Hash::new_unique());
+        let wire_transaction = bincode::serialize(&transaction).unwrap();
+        let submitter = IpAddr::from([10, 0, 0, 1]);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sendTransaction",
+            "params": [base64::encode(&wire_transaction), {"encoding": "base64"}],
+        });
+        let reply = proxy.handle_request(submitter, &request.to_string());
+        assert_eq!(reply["result"], transaction.signatures[0].to_string());
+
+        let mut packet = [0; PACKET_DATA_SIZE];
+        let (len, _) = tpu.recv_from(&mut packet).unwrap();
+        assert_eq!(&packet[..len], &wire_transaction[..]);
+
+        // A tampered transaction is rejected without being forwarded
+        let mut tampered = transaction;
+        tampered.message.recent_blockhash = Hash::new_unique();
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "sendTransaction",
+            "params": [bs58::encode(bincode::serialize(&tampered).unwrap()).into_string()],
+        });
+        let reply = proxy.handle_request(submitter, &request.to_string());
+        assert_eq!(reply["error"]["code"], INVALID_PARAMS);
+
+        let reply = proxy.handle_request(submitter, "not json");
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+
+        assert_eq!(
+            proxy.submitter_stats()[&submitter],
+            SubmitterStats {
+                received: 2,
+                forwarded: 1,
+                rejected: 1,
+                packets: 1,
+            }
+        );
+    }
+}
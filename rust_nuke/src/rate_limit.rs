@@ -0,0 +1,336 @@
+use {
+    log::*,
+    std::{
+        fmt,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Mutex,
+        },
+        thread::sleep,
+        time::{Duration, Instant},
+    },
+};
+
+// The bucket holds at most this much time's worth of tokens, so a sender that falls behind
+// can only catch up with a short burst
+const BURST_WINDOW: Duration = Duration::from_millis(100);
+
+// Longest a caller sleeps before checking the bucket again. The target rate can change under
+// a waiting caller, so it can't simply sleep until enough tokens would have accumulated.
+const MAX_WAIT: Duration = Duration::from_millis(10);
+
+/// How the target send rate, in transactions per second, changes over a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateProfile {
+    Constant {
+        tps: f64,
+    },
+    /// Ramp from `start_tps` to `end_tps` over `duration`, then hold `end_tps`
+    Linear {
+        start_tps: f64,
+        end_tps: f64,
+        duration: Duration,
+    },
+    /// Start at `start_tps` and add `step_tps` every `step_duration`
+    Step {
+        start_tps: f64,
+        step_tps: f64,
+        step_duration: Duration,
+    },
+    /// Hold `base_tps`, except for bursting to `spike_tps` for `duration` starting `at` into
+    /// the run
+    Spike {
+        base_tps: f64,
+        spike_tps: f64,
+        at: Duration,
+        duration: Duration,
+    },
+}
+
+impl RateProfile {
+    /// Target rate `elapsed` into the run
+    pub fn tps_at(&self, elapsed: Duration) -> f64 {
+        match *self {
+            Self::Constant { tps } => tps,
+            Self::Linear {
+                start_tps,
+                end_tps,
+                duration,
+            } => {
+                let progress = if duration.is_zero() {
+                    1.0
+                } else {
+                    (elapsed.as_secs_f64() / duration.as_secs_f64()).min(1.0)
+                };
+                start_tps + (end_tps - start_tps) * progress
+            }
+            Self::Step {
+                start_tps,
+                step_tps,
+                step_duration,
+            } => {
+                let steps = if step_duration.is_zero() {
+                    0
+                } else {
+                    (elapsed.as_secs_f64() / step_duration.as_secs_f64()) as u64
+                };
+                start_tps + step_tps * steps as f64
+            }
+            Self::Spike {
+                base_tps,
+                spike_tps,
+                at,
+                duration,
+            } => {
+                if elapsed >= at && elapsed < at + duration {
+                    spike_tps
+                } else {
+                    base_tps
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for RateProfile {
+    type Err = String;
+
+    /// Parse `TPS`, `constant:TPS`, `linear:START:END:SECS`, `step:START:STEP:SECS` or
+    /// `spike:BASE:PEAK:AT_SECS:SECS`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let kind = parts.next().unwrap_or_default();
+        let values = parts
+            .map(|value| {
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite() && *value >= 0.0)
+                    .ok_or_else(|| format!("invalid rate profile value {:?}", value))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let secs = Duration::from_secs_f64;
+        let profile = match (kind, values.as_slice()) {
+            (tps, []) => Self::Constant {
+                tps: tps
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("unknown rate profile {:?}", s))?,
+            },
+            ("constant", [tps]) => Self::Constant { tps: *tps },
+            ("linear", [start_tps, end_tps, duration]) => Self::Linear {
+                start_tps: *start_tps,
+                end_tps: *end_tps,
+                duration: secs(*duration),
+            },
+            ("step", [start_tps, step_tps, step_duration]) => Self::Step {
+                start_tps: *start_tps,
+                step_tps: *step_tps,
+                step_duration: secs(*step_duration),
+            },
+            ("spike", [base_tps, spike_tps, at, duration]) => Self::Spike {
+                base_tps: *base_tps,
+                spike_tps: *spike_tps,
+                at: secs(*at),
+                duration: secs(*duration),
+            },
+            _ => {
+                return Err(format!(
+                    "expected TPS, constant:TPS, linear:START:END:SECS, step:START:STEP:SECS \
+                     or spike:BASE:PEAK:AT_SECS:SECS, got {:?}",
+                    s
+                ))
+            }
+        };
+        // A rate of 0 would hold the senders back for good
+        let rates = match profile {
+            Self::Constant { tps } => vec![tps],
+            Self::Linear {
+                start_tps, end_tps, ..
+            } => vec![start_tps, end_tps],
+            Self::Step { start_tps, .. } => vec![start_tps],
+            Self::Spike {
+                base_tps,
+                spike_tps,
+                ..
+            } => vec![base_tps, spike_tps],
+        };
+        if rates.iter().all(|tps| is_valid_rate(*tps)) {
+            Ok(profile)
+        } else {
+            Err(format!("rates must be positive, got {:?}", s))
+        }
+    }
+}
+
+impl fmt::Display for RateProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Constant { tps } => write!(f, "constant:{}", tps),
+            Self::Linear {
+                start_tps,
+                end_tps,
+                duration,
+            } => write!(
+                f,
+                "linear:{}:{}:{}",
+                start_tps,
+                end_tps,
+                duration.as_secs_f64()
+            ),
+            Self::Step {
+                start_tps,
+                step_tps,
+                step_duration,
+            } => write!(
+                f,
+                "step:{}:{}:{}",
+                start_tps,
+                step_tps,
+                step_duration.as_secs_f64()
+            ),
+            Self::Spike {
+                base_tps,
+                spike_tps,
+                at,
+                duration,
+            } => write!(
+                f,
+                "spike:{}:{}:{}:{}",
+                base_tps,
+                spike_tps,
+                at.as_secs_f64(),
+                duration.as_secs_f64()
+            ),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket shared by the sender threads, refilled at the rate the profile sets for the
+/// current point in the run
+pub struct RateLimiter {
+    profile: RateProfile,
+    start: Instant,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(profile: RateProfile) -> Self {
+        let start = Instant::now();
+        Self {
+            profile,
+            start,
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                last_refill: start,
+            }),
+        }
+    }
+
+    /// Block until `count` transactions may be sent and take their tokens. Returns the
+    /// target rate they were sent at, or `None` if `exit_signal` was set while waiting.
+    pub fn acquire(&self, count: usize, exit_signal: &AtomicBool) -> Option<f64> {
+        let count = count as f64;
+        loop {
+            if exit_signal.load(Ordering::Relaxed) {
+                return None;
+            }
+            let (tps, wait) = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let tps = self.profile.tps_at(now.duration_since(self.start));
+                let refill = tps * now.duration_since(bucket.last_refill).as_secs_f64();
+                // Always let a full batch through eventually, even one bigger than a burst
+                let capacity = (tps * BURST_WINDOW.as_secs_f64()).max(count);
+                bucket.tokens = (bucket.tokens + refill).min(capacity);
+                bucket.last_refill = now;
+                if bucket.tokens >= count {
+                    bucket.tokens -= count;
+                    return Some(tps);
+                }
+                let wait = if tps > 0.0 {
+                    Duration::from_secs_f64((count - bucket.tokens) / tps)
+                } else {
+                    MAX_WAIT
+                };
+                (tps, wait)
+            };
+            trace!("rate limited at {:.0} tps, waiting {:?}", tps, wait);
+            sleep(wait.min(MAX_WAIT));
+        }
+    }
+}
+
+/// Whether `tps` is a rate transactions can be sent at
+pub fn is_valid_rate(tps: f64) -> bool {
+    tps.is_finite() && tps > 0.0
+}
+
+/// Bucket a target rate to two significant digits, so a ramp reports a readable number of
+/// landing rate rows rather than one per distinct rate
+pub fn rate_tier(tps: f64) -> u64 {
+    let tps = tps.round() as u64;
+    let mut scale = 1;
+    while tps / scale >= 100 {
+        scale *= 10;
+    }
+    tps / scale * scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_profiles() {
+        let secs = Duration::from_secs;
+        let linear: RateProfile = "linear:100:300:10".parse().unwrap();
+        assert_eq!(linear.tps_at(secs(0)), 100.0);
+        assert_eq!(linear.tps_at(secs(5)), 200.0);
+        assert_eq!(linear.tps_at(secs(20)), 300.0);
+
+        let step: RateProfile = "step:100:50:2".parse().unwrap();
+        assert_eq!(step.tps_at(secs(1)), 100.0);
+        assert_eq!(step.tps_at(secs(5)), 200.0);
+
+        let spike: RateProfile = "spike:100:1000:5:2".parse().unwrap();
+        assert_eq!(spike.tps_at(secs(4)), 100.0);
+        assert_eq!(spike.tps_at(secs(6)), 1000.0);
+        assert_eq!(spike.tps_at(secs(7)), 100.0);
+
+        assert_eq!("250".parse(), Ok(RateProfile::Constant { tps: 250.0 }));
+        assert!("linear:1:2".parse::<RateProfile>().is_err());
+        for invalid in [
+            "0",
+            "-5",
+            "NaN",
+            "inf",
+            "constant:0",
+            "linear:0:100:10",
+            "spike:1:0:1:1",
+        ] {
+            assert!(invalid.parse::<RateProfile>().is_err(), "{}", invalid);
+        }
+        assert_eq!(rate_tier(1234.0), 1200);
+        assert_eq!(rate_tier(56.0), 56);
+    }
+
+    #[test]
+    fn test_rate_limiter_stops_waiting_on_exit() {
+        let limiter = RateLimiter::new(RateProfile::Constant { tps: 100.0 });
+        let exit_signal = AtomicBool::new(false);
+        assert_eq!(limiter.acquire(1, &exit_signal), Some(100.0));
+
+        exit_signal.store(true, Ordering::Relaxed);
+        let start = Instant::now();
+        assert_eq!(limiter.acquire(1, &exit_signal), None);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
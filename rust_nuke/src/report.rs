@@ -0,0 +1,389 @@
+use {
+    crate::{bench::BenchTpsStats, cli::Config, latency::LatencyStats},
+    serde::Serialize,
+    solana_sdk::{signature::Signer, timing::duration_as_s},
+    std::{
+        fs::{File, OpenOptions},
+        io::{self, Write},
+        path::Path,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    /// Pick the format from the report path's extension, defaulting to JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Self::Csv,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// The parts of the run configuration that matter when comparing runs
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportConfig {
+    pub command: String,
+    pub json_rpc_url: String,
+    pub client: String,
+    pub funding_pubkey: String,
+    pub keypair_namespace: Option<String>,
+    pub keypair_count: usize,
+    pub lamports_per_account: u64,
+    pub commitment: String,
+    pub threads: usize,
+    pub duration_secs: Option<u64>,
+    pub tx_limit: Option<usize>,
+    pub sustained: bool,
+    pub workload: String,
+    pub compute_unit_limit: Option<u32>,
+    /// Fixed price or `START-END:STEPS` sweep, in micro-lamports per compute unit
+    pub compute_unit_price: Option<String>,
+    pub durable_nonce: bool,
+    pub rate_profile: Option<String>,
+}
+
+impl From<&Config> for ReportConfig {
+    fn from(config: &Config) -> Self {
+        Self {
+            command: format!("{:?}", config.command).to_lowercase(),
+            json_rpc_url: config.json_rpc_url.clone(),
+            client: format!("{:?}", config.external_client_type),
+            funding_pubkey: config.id.pubkey().to_string(),
+            keypair_namespace: config.keypair_namespace.clone(),
+            keypair_count: config.keypair_count,
+            lamports_per_account: config.num_lamports_per_account,
+            commitment: format!("{:?}", config.commitment_config.commitment).to_lowercase(),
+            threads: config.threads,
+            // The default duration means "run forever", which isn't worth recording
+            duration_secs: (config.duration.as_secs() != u64::MAX)
+                .then(|| config.duration.as_secs()),
+            tx_limit: config.tx_limit,
+            sustained: config.sustained,
+            workload: config.workload.to_string(),
+            compute_unit_limit: config.compute_budget.compute_unit_limit,
+            compute_unit_price: config
+                .compute_budget
+                .compute_unit_price
+                .map(|price| price.to_string()),
+            durable_nonce: config.use_durable_nonce,
+            rate_profile: config.rate_profile.map(|profile| profile.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportTpsSample {
+    pub elapsed_secs: f32,
+    pub txs: u64,
+    pub tps: f32,
+}
+
+/// Everything a single run produced, in a form that can be written as JSON or CSV
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub config: ReportConfig,
+    /// Lamports the funding keypair spent funding the derived keypairs, fees included
+    pub funding_cost_lamports: u64,
+    /// Lamports the derived keypairs spent on fees while spamming, priority fees included.
+    /// Unknown when replaying a corpus, whose fee payers aren't ours to check.
+    pub fees_paid_lamports: Option<u64>,
+    /// Average of `fees_paid_lamports` over the transactions that landed
+    pub fee_per_transaction: Option<u64>,
+    pub transactions_sent: usize,
+    pub transactions_expired: usize,
+    pub transactions_confirmed: u64,
+    /// Landed, but with an error
+    pub transactions_failed: u64,
+    /// Never seen by the cluster
+    pub transactions_dropped: u64,
+    /// Still in flight when tracking stopped
+    pub transactions_pending: u64,
+    pub elapsed_secs: f32,
+    pub max_tps: f32,
+    pub overall_tps: f32,
+    pub cluster_transactions: u64,
+    pub tps_samples: Vec<ReportTpsSample>,
+    pub latency: LatencyStats,
+    /// Slots the run started and finished at, which the stats command can report on
+    pub start_slot: Option<u64>,
+    pub end_slot: Option<u64>,
+}
+
+impl RunReport {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: ReportConfig::from(config),
+            funding_cost_lamports: 0,
+            fees_paid_lamports: None,
+            fee_per_transaction: None,
+            transactions_sent: 0,
+            transactions_expired: 0,
+            transactions_confirmed: 0,
+            transactions_failed: 0,
+            transactions_dropped: 0,
+            transactions_pending: 0,
+            elapsed_secs: 0.0,
+            max_tps: 0.0,
+            overall_tps: 0.0,
+            cluster_transactions: 0,
+            tps_samples: vec![],
+            latency: LatencyStats::default(),
+            start_slot: None,
+            end_slot: None,
+        }
+    }
+
+    /// Record the outcome of a spam run. Landed transactions come from the signature tracker.
+    /// Every generated transaction moves lamports between our own keypairs, so the only
+    /// lamports that leave them are fees, and `fees_paid` is what they actually paid.
+    pub fn record_bench(&mut self, stats: &BenchTpsStats, fees_paid: Option<u64>) {
+        // Failed transactions pay their fee too, so average over everything that landed
+        let landed = stats.latency.processed.count as u64;
+        self.fees_paid_lamports = fees_paid;
+        self.fee_per_transaction = fees_paid.and_then(|fees_paid| fees_paid.checked_div(landed));
+        self.transactions_sent = stats.tx_sent;
+        self.transactions_expired = stats.tx_expired;
+        self.transactions_confirmed = stats.latency.confirmed.count as u64;
+        self.transactions_failed = stats.latency.failed as u64;
+        self.transactions_dropped = stats.latency.dropped as u64;
+        self.transactions_pending = stats.latency.pending as u64;
+        self.elapsed_secs = duration_as_s(&stats.elapsed);
+        self.max_tps = stats.sample_stats.tps;
+        self.overall_tps = stats.sample_stats.overall_tps();
+        self.cluster_transactions = stats.sample_stats.txs;
+        self.tps_samples = stats
+            .sample_stats
+            .samples
+            .iter()
+            .map(|sample| ReportTpsSample {
+                elapsed_secs: duration_as_s(&sample.elapsed),
+                txs: sample.txs,
+                tps: sample.tps,
+            })
+            .collect();
+        self.latency = stats.latency.clone();
+    }
+
+    pub fn write(&self, path: &Path, format: ReportFormat) -> io::Result<()> {
+        match format {
+            ReportFormat::Json => {
+                let file = File::create(path)?;
+                serde_json::to_writer_pretty(file, self)?;
+                Ok(())
+            }
+            ReportFormat::Csv => self.append_csv(path),
+        }
+    }
+
+    /// CSV reports hold one row per run so several runs can share a file; the header is only
+    /// written when the file is new or empty.
+    fn append_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", Self::csv_header())?;
+        }
+        writeln!(file, "{}", self.csv_row())
+    }
+
+    fn csv_header() -> &'static str {
+        "command,json_rpc_url,client,funding_pubkey,keypair_namespace,keypair_count,\
+         lamports_per_account,commitment,threads,duration_secs,tx_limit,sustained,workload,\
+         compute_unit_limit,compute_unit_price,durable_nonce,rate_profile,funding_cost_lamports,\
+         fees_paid_lamports,fee_per_transaction,transactions_sent,transactions_expired,\
+         transactions_confirmed,transactions_failed,transactions_dropped,transactions_pending,\
+         elapsed_secs,max_tps,overall_tps,cluster_transactions,processed_p50_ms,\
+         processed_p90_ms,processed_p99_ms,confirmed_p50_ms,confirmed_p90_ms,confirmed_p99_ms,\
+         finalized_p50_ms,finalized_p90_ms,finalized_p99_ms,tps_samples,fee_tiers,rate_tiers,\
+         start_slot,end_slot"
+    }
+
+    fn csv_row(&self) -> String {
+        let config = &self.config;
+        // Samples don't fit in a single cell as a list, so join them as elapsed:tps pairs
+        let tps_samples = self
+            .tps_samples
+            .iter()
+            .map(|sample| format!("{:.2}:{:.2}", sample.elapsed_secs, sample.tps))
+            .collect::<Vec<_>>()
+            .join(";");
+        // Likewise fee tiers, as price:confirmed/sent
+        let fee_tiers = self
+            .latency
+            .fee_tiers
+            .iter()
+            .map(|tier| {
+                format!(
+                    "{}:{}/{}",
+                    tier.compute_unit_price, tier.confirmed, tier.sent
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+        // And rate tiers, as target_tps:confirmed/sent
+        let rate_tiers = self
+            .latency
+            .rate_tiers
+            .iter()
+            .map(|tier| format!("{}:{}/{}", tier.target_tps, tier.confirmed, tier.sent))
+            .collect::<Vec<_>>()
+            .join(";");
+        [
+            config.command.clone(),
+            csv_escape(&config.json_rpc_url),
+            config.client.clone(),
+            config.funding_pubkey.clone(),
+            csv_escape(config.keypair_namespace.as_deref().unwrap_or_default()),
+            config.keypair_count.to_string(),
+            config.lamports_per_account.to_string(),
+            config.commitment.clone(),
+            config.threads.to_string(),
+            optional(config.duration_secs),
+            optional(config.tx_limit),
+            config.sustained.to_string(),
+            csv_escape(&config.workload),
+            optional(config.compute_unit_limit),
+            optional(config.compute_unit_price.clone()),
+            config.durable_nonce.to_string(),
+            optional(config.rate_profile.clone()),
+            self.funding_cost_lamports.to_string(),
+            optional(self.fees_paid_lamports),
+            optional(self.fee_per_transaction),
+            self.transactions_sent.to_string(),
+            self.transactions_expired.to_string(),
+            self.transactions_confirmed.to_string(),
+            self.transactions_failed.to_string(),
+            self.transactions_dropped.to_string(),
+            self.transactions_pending.to_string(),
+            format!("{:.2}", self.elapsed_secs),
+            format!("{:.2}", self.max_tps),
+            format!("{:.2}", self.overall_tps),
+            self.cluster_transactions.to_string(),
+            self.latency.processed.p50_ms.to_string(),
+            self.latency.processed.p90_ms.to_string(),
+            self.latency.processed.p99_ms.to_string(),
+            self.latency.confirmed.p50_ms.to_string(),
+            self.latency.confirmed.p90_ms.to_string(),
+            self.latency.confirmed.p99_ms.to_string(),
+            self.latency.finalized.p50_ms.to_string(),
+            self.latency.finalized.p90_ms.to_string(),
+            self.latency.finalized.p99_ms.to_string(),
+            tps_samples,
+            fee_tiers,
+            rate_tiers,
+            optional(self.start_slot),
+            optional(self.end_slot),
+        ]
+        .join(",")
+    }
+}
+
+/// Slots a run recorded in the JSON report at `path`
+pub fn report_slot_range(path: &Path) -> io::Result<(u64, u64)> {
+    let report: serde_json::Value = serde_json::from_reader(File::open(path)?)?;
+    match (report["start_slot"].as_u64(), report["end_slot"].as_u64()) {
+        (Some(start_slot), Some(end_slot)) => Ok((start_slot, end_slot)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the report has no start and end slot",
+        )),
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains(',') || field.contains('"') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs};
+
+    /// Fields in a CSV line, skipping commas inside quoted fields
+    fn field_count(line: &str) -> usize {
+        let mut quoted = false;
+        1 + line
+            .chars()
+            .filter(|c| {
+                if *c == '"' {
+                    quoted = !quoted;
+                }
+                *c == ',' && !quoted
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_csv_header_matches_row() {
+        let config = Config {
+            json_rpc_url: "http://localhost:8899/?cluster=\"a,b\"".to_string(),
+            tx_limit: Some(1000),
+            ..Config::default()
+        };
+        let mut report = RunReport::new(&config);
+        report.tps_samples = vec![
+            ReportTpsSample {
+                elapsed_secs: 1.0,
+                txs: 100,
+                tps: 100.0,
+            },
+            ReportTpsSample {
+                elapsed_secs: 2.0,
+                txs: 300,
+                tps: 200.0,
+            },
+        ];
+
+        let path = std::env::temp_dir().join(format!("report-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        report.write(&path, ReportFormat::Csv).unwrap();
+        report.write(&path, ReportFormat::Csv).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // The header is only written once, and every row has a field per column
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], RunReport::csv_header());
+        let columns = field_count(lines[0]);
+        assert!(lines[1..].iter().all(|line| field_count(line) == columns));
+    }
+
+    #[test]
+    fn test_record_bench() {
+        let mut stats = BenchTpsStats {
+            tx_sent: 11,
+            ..BenchTpsStats::default()
+        };
+        stats.latency.processed.count = 6;
+        stats.latency.confirmed.count = 5;
+        stats.latency.failed = 1;
+        stats.latency.dropped = 3;
+        stats.latency.pending = 2;
+
+        let mut report = RunReport::new(&Config::default());
+        report.record_bench(&stats, Some(60_000));
+        // Transactions that failed or are still pending weren't dropped
+        assert_eq!(report.transactions_confirmed, 5);
+        assert_eq!(report.transactions_failed, 1);
+        assert_eq!(report.transactions_dropped, 3);
+        assert_eq!(report.transactions_pending, 2);
+        // The failed transaction paid its fee as well
+        assert_eq!(report.fee_per_transaction, Some(10_000));
+
+        report.record_bench(&stats, None);
+        assert_eq!(report.fees_paid_lamports, None);
+        assert_eq!(report.fee_per_transaction, None);
+    }
+}
@@ -2,6 +2,10 @@ use {
     crate::bench::generate_keypairs,
     crate::bench_tps_client::*,
     crate::blockhash::*,
+    crate::keypair_store::{check_store_owner, read_keypairs, read_manifest, store_exists},
+    crate::latency::MAX_SIGNATURES_PER_STATUS_REQUEST,
+    crate::nonce::{get_nonce_accounts, nonce_keypair, MAX_MULTIPLE_ACCOUNTS},
+    crate::priority_fee::ComputeBudget,
     log::{debug, error, info, trace, warn},
     rayon::prelude::*,
     solana_client::{
@@ -18,16 +22,17 @@ use {
         message::Message,
         native_token::Sol,
         pubkey::Pubkey,
-        signature::{read_keypair_file, Keypair, Signer},
+        signature::{read_keypair_file, Keypair, Signature, Signer},
         system_instruction,
         system_transaction,
         // timing::{duration_as_ms, duration_as_s, duration_as_us, timestamp},
         transaction::Transaction,
     },
     std::{
-        collections::{HashSet, VecDeque},
+        collections::{HashMap, HashSet, VecDeque},
         fs::File,
         io::Read,
+        path::Path,
         process::exit,
         // process::exit,
         sync::{
@@ -39,114 +44,415 @@ use {
     },
 };
 
-fn make<T: 'static + BenchTpsClient + Send + Sync>(
-    client: Arc<T>,
-    to_fund: &[(&Keypair, Vec<(Pubkey, u64)>)],
-) {
-    let blockhash = Arc::new(RwLock::new(get_latest_blockhash(client.as_ref())));
-
-    let exit_signal = Arc::new(AtomicBool::new(false));
-
-    let blockhash_thread = {
-        let exit_signal = exit_signal.clone();
-        let blockhash = blockhash.clone();
-        let client = client.clone();
-        // let id = id.pubkey();
-        Builder::new()
-            .name("solana-blockhash-poller".to_string())
-            .spawn(move || {
-                poll_blockhash(&exit_signal, &blockhash, &client);
-            })
-            .unwrap()
-    };
+// Sweep transactions handed to each send_batch call
+const DEFUND_BATCH_SIZE: usize = 256;
 
-    let mut to_fund_txs: Vec<(&Keypair, Transaction)> = to_fund
-        .par_iter()
-        .map(|(k, t)| {
-            let instructions = system_instruction::transfer_many(&k.pubkey(), t);
-            let message = Message::new(&instructions, Some(&k.pubkey()));
-            (*k, Transaction::new_unsigned(message))
-        })
-        .collect();
+// Rounds of sweeping before giving up on accounts that still hold lamports
+const DEFUND_RETRIES: usize = 5;
 
-    to_fund_txs.par_iter_mut().for_each(|(k, tx)| {
-        tx.sign(&[*k], *blockhash.read().unwrap());
-    });
+// How long a sweep transaction can take to confirm, which is as long as its blockhash is valid
+const DEFUND_CONFIRM_TIMEOUT: Duration =
+    Duration::from_millis(MAX_PROCESSING_AGE as u64 * DEFAULT_MS_PER_SLOT);
 
-    let batch: Vec<_> = to_fund_txs
-        .iter()
-        .map(|(_keypair, tx)| tx.clone())
-        .collect();
+/// What a defund sweep returned to the funding keypair
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefundSummary {
+    /// Lamports returned to the funding keypair, net of fees
+    pub reclaimed: u64,
+    /// Accounts emptied, nonce accounts not counted separately
+    pub swept: usize,
+    /// Accounts that still hold lamports, including ones too poor to pay their own fee
+    pub remaining: usize,
+    pub remaining_lamports: u64,
+}
 
-    println!("sending batch");
-    client.send_batch(batch).expect("transfer");
+/// Balance of each of `pubkeys`, 0 for accounts that don't exist
+fn get_balances<T: BenchTpsClient>(client: &T, pubkeys: &[Pubkey]) -> Result<Vec<u64>> {
+    let mut balances = Vec::with_capacity(pubkeys.len());
+    for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        balances.extend(
+            client
+                .get_multiple_accounts(chunk)?
+                .into_iter()
+                .map(|account| account.map_or(0, |account| account.lamports)),
+        );
+    }
+    Ok(balances)
 }
 
+/// Wait until each of `signatures` is confirmed, has failed, or can no longer land because
+/// its blockhash expired. Returns whether each one was confirmed without an error.
+fn confirm_signatures<T: BenchTpsClient>(client: &T, signatures: &[Signature]) -> Vec<bool> {
+    let mut confirmed = vec![false; signatures.len()];
+    let mut waiting: Vec<usize> = (0..signatures.len()).collect();
+    let start = Instant::now();
+    while !waiting.is_empty() && start.elapsed() < DEFUND_CONFIRM_TIMEOUT {
+        sleep(Duration::from_millis(DEFAULT_MS_PER_SLOT));
+        let mut still_waiting = Vec::with_capacity(waiting.len());
+        for chunk in waiting.chunks(MAX_SIGNATURES_PER_STATUS_REQUEST) {
+            let chunk_signatures: Vec<Signature> = chunk.iter().map(|i| signatures[*i]).collect();
+            let statuses = match client.get_signature_statuses(&chunk_signatures) {
+                Ok(statuses) => statuses,
+                Err(err) => {
+                    info!("Couldn't get signature statuses: {:?}", err);
+                    still_waiting.extend_from_slice(chunk);
+                    continue;
+                }
+            };
+            for (i, status) in chunk.iter().zip(statuses) {
+                match status {
+                    Some(status) if status.err.is_some() => {}
+                    Some(status) if status.satisfies_commitment(CommitmentConfig::confirmed()) => {
+                        confirmed[*i] = true
+                    }
+                    _ => still_waiting.push(*i),
+                }
+            }
+        }
+        waiting = still_waiting;
+    }
+    confirmed
+}
+
+/// Return every lamport held by the derived keypairs, and by the nonce accounts of durable
+/// nonce runs, to `funding_key`. Each account pays its own fee and is drained to zero.
+/// Accounts that don't confirm are read again and resent with a fresh blockhash. Fails with
+/// `FundingIncomplete` if any account that could pay its own fee still holds lamports, or
+/// with `BlockhashStuck` once what was reclaimed so far has been reported.
 pub fn defund_keypairs<T: 'static + BenchTpsClient + Send + Sync>(
     client: Arc<T>,
     funding_key: &Keypair,
     keypair_count: usize,
-) {
-    let blockhash = Arc::new(RwLock::new(get_latest_blockhash(client.as_ref())));
-
-    let exit_signal = Arc::new(AtomicBool::new(false));
-
-    let blockhash_thread = {
-        let exit_signal = exit_signal.clone();
-        let blockhash = blockhash.clone();
-        let client = client.clone();
-        // let id = id.pubkey();
-        Builder::new()
-            .name("solana-blockhash-poller".to_string())
-            .spawn(move || {
-                poll_blockhash(&exit_signal, &blockhash, &client);
-            })
-            .unwrap()
+    compute_budget: &ComputeBudget,
+    keypair_store: Option<&Path>,
+    namespace: Option<&str>,
+) -> Result<DefundSummary> {
+    // A store holds exactly the keypairs that were funded, including any a later run with a
+    // different keypair count wouldn't derive. One made for another funder or namespace holds
+    // someone else's keypairs.
+    let keypairs = match keypair_store.filter(|dir| store_exists(dir)) {
+        Some(dir) => {
+            check_store_owner(dir, &read_manifest(dir)?, funding_key, namespace)?;
+            read_keypairs(dir)?
+        }
+        None => generate_keypairs(funding_key, keypair_count as u64, namespace).0,
     };
+    let budget_instructions = compute_budget.initial_instructions();
 
-    let (mut keypairs, extra) = generate_keypairs(funding_key, keypair_count as u64);
+    // Nonce accounts left by durable nonce runs are closed along with their authorities
+    let nonce_pubkeys: Vec<Pubkey> = keypairs
+        .par_iter()
+        .map(|k| nonce_keypair(k).pubkey())
+        .collect();
+
+    let mut summary = DefundSummary::default();
+    let mut pending: Vec<usize> = (0..keypairs.len()).collect();
+    let mut stranded: Vec<(usize, u64)> = Vec::new();
+    let mut last_blockhash = None;
+    let mut blockhash_stuck = false;
+    for tries in 0..DEFUND_RETRIES {
+        // Read everything again each round. A transfer that wasn't confirmed in time may still
+        // have landed, and failed ones may have been charged a fee.
+        let pubkeys: Vec<Pubkey> = pending.iter().map(|i| keypairs[*i].pubkey()).collect();
+        let pending_nonce_pubkeys: Vec<Pubkey> =
+            pending.iter().map(|i| nonce_pubkeys[*i]).collect();
+        let (balances, nonce_accounts) =
+            match get_balances(client.as_ref(), &pubkeys).and_then(|balances| {
+                get_nonce_accounts(client.as_ref(), &pending_nonce_pubkeys)
+                    .map(|nonce_accounts| (balances, nonce_accounts))
+            }) {
+                Ok(accounts) => accounts,
+                Err(err) => {
+                    warn!("failed to get balances: {:?}", err);
+                    sleep(Duration::from_secs(1));
+                    continue;
+                }
+            };
+
+        // Resending with the blockhash that already failed to land would be pointless
+        let blockhash = match last_blockhash {
+            Some(last_blockhash) => match get_new_latest_blockhash(&client, &last_blockhash) {
+                Some(blockhash) => blockhash,
+                None => {
+                    // Still report what was reclaimed and what's left below
+                    blockhash_stuck = true;
+                    break;
+                }
+            },
+            None => get_latest_blockhash(client.as_ref()),
+        };
+        last_blockhash = Some(blockhash);
+        // Messages only differ in their amounts and whether they close a nonce account, so
+        // there are at most two fees to look up
+        let mut fees: HashMap<bool, u64> = HashMap::new();
+        let mut sweeps: Vec<(usize, u64, Transaction)> = Vec::new();
+        let mut unswept: Vec<usize> = Vec::new();
+        for ((i, balance), nonce_account) in pending.iter().zip(balances).zip(nonce_accounts) {
+            let cur_key = &keypairs[*i];
+            let nonce_lamports = nonce_account.map_or(0, |nonce_account| nonce_account.lamports);
+            if balance == 0 && nonce_lamports == 0 {
+                continue;
+            }
 
-    let pubkey_group = keypairs.iter().map(|x| x.pubkey()).collect::<Vec<_>>();
-    // println!("group keypair: {:?}", pubkey_group);
+            let mut instructions = budget_instructions.clone();
+            if nonce_lamports > 0 {
+                // Withdrawing everything closes the nonce account
+                instructions.push(system_instruction::withdraw_nonce_account(
+                    &nonce_pubkeys[*i],
+                    &cur_key.pubkey(),
+                    &cur_key.pubkey(),
+                    nonce_lamports,
+                ));
+            }
+            let transfer_index = instructions.len();
+            instructions.push(system_instruction::transfer(
+                &cur_key.pubkey(),
+                &funding_key.pubkey(),
+                balance + nonce_lamports,
+            ));
+            let mut message =
+                Message::new_with_blockhash(&instructions, Some(&cur_key.pubkey()), &blockhash);
+
+            let fee = match fees.get(&(nonce_lamports > 0)) {
+                Some(fee) => *fee,
+                None => match client.get_fee_for_message(&message) {
+                    Ok(fee) => *fees.entry(nonce_lamports > 0).or_insert(fee),
+                    Err(err) => {
+                        warn!("failed to get fee for message: {:?}", err);
+                        unswept.push(*i);
+                        continue;
+                    }
+                },
+            };
+            // The fee is charged before the nonce account is withdrawn from
+            if balance < fee {
+                stranded.push((*i, balance + nonce_lamports));
+                continue;
+            }
+            let amount = balance + nonce_lamports - fee;
+            instructions[transfer_index] =
+                system_instruction::transfer(&cur_key.pubkey(), &funding_key.pubkey(), amount);
+            message =
+                Message::new_with_blockhash(&instructions, Some(&cur_key.pubkey()), &blockhash);
+            sweeps.push((*i, amount, Transaction::new(&[cur_key], message, blockhash)));
+        }
+        pending = unswept;
+        if sweeps.is_empty() {
+            if pending.is_empty() {
+                break;
+            }
+            continue;
+        }
 
-    for i in 0..keypairs.len() {
-        let cur_key = &keypairs[i];
-        let key_balance = client.get_balance(&cur_key.pubkey()).unwrap_or(0);
         println!(
-            "\n\nBEFORE\naccount {}: address: {}, balance: {}",
-            i,
-            &cur_key.pubkey(),
-            key_balance
+            "{} {} accounts",
+            if tries == 0 { "sweeping" } else { "retrying" },
+            sweeps.len()
         );
+        let signatures: Vec<Signature> = sweeps.iter().map(|(_, _, tx)| tx.signatures[0]).collect();
+        for batch in sweeps.chunks(DEFUND_BATCH_SIZE) {
+            let transactions = batch.iter().map(|(_, _, tx)| tx.clone()).collect();
+            if let Err(err) = client.send_batch(transactions) {
+                warn!("send_batch in defund_keypairs failed: {:?}", err);
+            }
+        }
 
-        // let transfer_bal = 19 * key_balance / 20 as u64;
+        for ((i, amount, _tx), confirmed) in sweeps
+            .into_iter()
+            .zip(confirm_signatures(client.as_ref(), &signatures))
+        {
+            if confirmed {
+                summary.reclaimed += amount;
+                summary.swept += 1;
+            } else {
+                pending.push(i);
+            }
+        }
+        if pending.is_empty() {
+            break;
+        }
+    }
 
-        if key_balance > 5000 {
-            let transfer_bal = key_balance - 5000;
-            println!("transfer_bal: {}", transfer_bal);
-            let tx = system_transaction::transfer(
-                &cur_key,
-                &funding_key.pubkey(),
-                transfer_bal,
-                *blockhash.read().unwrap(),
+    // Whatever didn't confirm in the last round may still land, so check what's really left
+    let pending_pubkeys: Vec<Pubkey> = pending.iter().map(|i| keypairs[*i].pubkey()).collect();
+    // If they can't be read, assume nothing landed
+    let pending_balances: Vec<Option<u64>> = match get_balances(client.as_ref(), &pending_pubkeys) {
+        Ok(balances) => balances.into_iter().map(Some).collect(),
+        Err(err) => {
+            warn!("failed to get balances: {:?}", err);
+            vec![None; pending_pubkeys.len()]
+        }
+    };
+    // Dust too small to pay its own fee can't be swept, so only the rest counts as a failure
+    let unswept = pending_balances
+        .iter()
+        .filter(|balance| **balance != Some(0))
+        .count();
+    let stranded = stranded
+        .into_iter()
+        .map(|(i, lamports)| (i, Some(lamports)));
+    for (i, balance) in pending
+        .iter()
+        .copied()
+        .zip(pending_balances)
+        .chain(stranded)
+    {
+        if balance != Some(0) {
+            debug!(
+                "{} still holds {:?} lamports",
+                keypairs[i].pubkey(),
+                balance
             );
+            summary.remaining += 1;
+            summary.remaining_lamports += balance.unwrap_or(0);
+        }
+    }
+
+    println!(
+        "reclaimed {} lamports ({}) from {} accounts",
+        summary.reclaimed,
+        Sol(summary.reclaimed),
+        summary.swept
+    );
+    if summary.remaining > 0 {
+        warn!(
+            "{} accounts still hold {} lamports",
+            summary.remaining, summary.remaining_lamports
+        );
+    }
+    if blockhash_stuck {
+        return Err(BenchTpsError::BlockhashStuck);
+    }
+    if unswept > 0 {
+        return Err(BenchTpsError::FundingIncomplete { remaining: unswept });
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            bench_tps_client::mock_client::{MockCall, MockClient},
+            keypair_store::load_or_generate_keypairs,
+        },
+    };
 
-            let result = client.send_transaction(tx);
+    const BALANCE: u64 = 1_000_000;
+    // What MockClient charges a single signature transaction
+    const MOCK_FEE: u64 = 5000;
 
-            // println!("result {}", result.unwrap());
+    /// A client holding `BALANCE` in each of the `count` keypairs derived from the returned
+    /// funding keypair
+    fn funded_client(client: MockClient, count: u64) -> (Arc<MockClient>, Keypair, Vec<Keypair>) {
+        let id = Keypair::new();
+        let (keypairs, _extra) = generate_keypairs(&id, count, None);
+        for keypair in &keypairs {
+            client.set_balance(&keypair.pubkey(), BALANCE);
         }
+        (Arc::new(client), id, keypairs)
     }
 
-    sleep(Duration::from_millis(1000));
+    #[test]
+    fn test_defund_keypairs() {
+        let (client, id, keypairs) = funded_client(MockClient::default(), 4);
+        // Too little to pay its own fee, so it's left behind without failing the sweep
+        client.set_balance(&keypairs[3].pubkey(), MOCK_FEE - 1);
 
-    for i in 0..keypair_count {
-        let cur_key = &keypairs[i];
-        let key_balance = client.get_balance(&cur_key.pubkey()).unwrap_or(0);
-        println!(
-            "AFTER: \naccount {}: address: {}, balance: {}",
-            i,
-            &cur_key.pubkey(),
-            key_balance
+        let summary = defund_keypairs(
+            client.clone(),
+            &id,
+            4,
+            &ComputeBudget::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            summary,
+            DefundSummary {
+                reclaimed: 3 * (BALANCE - MOCK_FEE),
+                swept: 3,
+                remaining: 1,
+                remaining_lamports: MOCK_FEE - 1,
+            }
         );
+        assert_eq!(client.balance(&id.pubkey()), summary.reclaimed);
+        for keypair in &keypairs[..3] {
+            assert_eq!(client.balance(&keypair.pubkey()), 0);
+        }
+    }
+
+    #[test]
+    fn test_defund_keypairs_retries() {
+        let client = MockClient::default().with_latency(Duration::from_millis(1));
+        let (client, id, keypairs) = funded_client(client, 4);
+
+        // The first round can't read the balances, so the second one sweeps
+        client.fail_next(MockCall::GetMultipleAccounts, 1);
+        let summary = defund_keypairs(
+            client.clone(),
+            &id,
+            4,
+            &ComputeBudget::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(summary.swept, keypairs.len());
+        assert_eq!(summary.remaining, 0);
+        assert_eq!(
+            client.balance(&id.pubkey()),
+            keypairs.len() as u64 * (BALANCE - MOCK_FEE)
+        );
+    }
+
+    #[test]
+    fn test_defund_keypairs_failure() {
+        let (client, id, keypairs) = funded_client(MockClient::default(), 4);
+
+        // No sweep can be priced, so nothing is sent and every account still holds lamports
+        client.fail_next(MockCall::GetFeeForMessage, usize::MAX);
+        assert!(matches!(
+            defund_keypairs(
+                client.clone(),
+                &id,
+                4,
+                &ComputeBudget::default(),
+                None,
+                None
+            ),
+            Err(BenchTpsError::FundingIncomplete { remaining: 4 })
+        ));
+        assert!(!client.calls().contains(&MockCall::SendBatch));
+        for keypair in &keypairs {
+            assert_eq!(client.balance(&keypair.pubkey()), BALANCE);
+        }
+    }
+
+    #[test]
+    fn test_defund_keypairs_refuses_other_stores() {
+        let (client, id, _keypairs) = funded_client(MockClient::default(), 4);
+        let dir = std::env::temp_dir().join(format!("defund-store-{}", std::process::id()));
+        load_or_generate_keypairs(&id, 4, Some("alice"), Some(&dir)).unwrap();
+        let defund = |funding_key: &Keypair, namespace| {
+            defund_keypairs(
+                client.clone(),
+                funding_key,
+                4,
+                &ComputeBudget::default(),
+                Some(&dir),
+                namespace,
+            )
+        };
+
+        assert!(matches!(defund(&id, None), Err(BenchTpsError::IoError(_))));
+        assert!(matches!(
+            defund(&Keypair::new(), Some("alice")),
+            Err(BenchTpsError::IoError(_))
+        ));
+        assert!(client.calls().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,253 @@
+use {
+    crate::{
+        nonce::nonced_message,
+        nuke_program::{self, SpamArgs},
+    },
+    rand::{
+        distributions::{Distribution, WeightedIndex},
+        Rng,
+    },
+    solana_sdk::{
+        hash::Hash,
+        instruction::{AccountMeta, Instruction},
+        message::Message,
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+        system_instruction,
+        transaction::Transaction,
+    },
+    std::{fmt, ops::RangeInclusive, str::FromStr},
+};
+
+mod memo {
+    solana_sdk::declare_id!("MemoSq4gqABAXKb96qnH8TuNbsxZ3NS6bMpV4JtHkRc");
+}
+
+/// The kinds of transactions the generator can send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransactionKind {
+    /// `system_instruction::transfer` between two generated keypairs
+    Transfer,
+    /// The nuke program's compute heavy `spam` instruction
+    NukeSpam,
+    /// A memo that does nothing but cost a fee
+    Memo,
+}
+
+impl TransactionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Transfer => "transfer",
+            Self::NukeSpam => "spam",
+            Self::Memo => "memo",
+        }
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transfer" => Ok(Self::Transfer),
+            "spam" => Ok(Self::NukeSpam),
+            "memo" => Ok(Self::Memo),
+            _ => Err(format!(
+                "unknown transaction kind {:?}, expected transfer, spam or memo",
+                s
+            )),
+        }
+    }
+}
+
+/// Relative weight of each kind of transaction in a run, and the parameters to build them with
+#[derive(Debug, Clone)]
+pub struct Workload {
+    weights: Vec<(TransactionKind, u32)>,
+    index: WeightedIndex<u32>,
+    /// Lamports moved by transfers and by the spam instruction's inner transfer
+    pub transfer_amount: u64,
+    /// `loop_counter` of each spam instruction is drawn uniformly from this range
+    pub loop_counter: RangeInclusive<u16>,
+    pub nuke_program_id: Pubkey,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Self::new(vec![(TransactionKind::Transfer, 1)]).unwrap()
+    }
+}
+
+impl Workload {
+    pub fn new(weights: Vec<(TransactionKind, u32)>) -> Result<Self, String> {
+        let index = WeightedIndex::new(weights.iter().map(|(_kind, weight)| *weight))
+            .map_err(|err| format!("invalid workload weights: {}", err))?;
+        Ok(Self {
+            weights,
+            index,
+            transfer_amount: 1,
+            loop_counter: 85..=85,
+            nuke_program_id: nuke_program::id(),
+        })
+    }
+
+    /// Parse a mix such as `transfer=70,spam=20,memo=10`
+    pub fn parse_weights(spec: &str) -> Result<Vec<(TransactionKind, u32)>, String> {
+        spec.split(',')
+            .map(|entry| {
+                let (kind, weight) = entry
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected KIND=WEIGHT, got {:?}", entry))?;
+                let weight = weight
+                    .trim()
+                    .parse()
+                    .map_err(|err| format!("invalid weight {:?}: {}", weight, err))?;
+                Ok((kind.trim().parse()?, weight))
+            })
+            .collect()
+    }
+
+    pub fn weights(&self) -> &[(TransactionKind, u32)] {
+        &self.weights
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> TransactionKind {
+        self.weights[self.index.sample(rng)].0
+    }
+
+    /// Instructions for one transaction of `kind` paid for and signed by `from`
+    pub fn instructions<R: Rng>(
+        &self,
+        kind: TransactionKind,
+        from: &Pubkey,
+        to: &Pubkey,
+        rng: &mut R,
+    ) -> Vec<Instruction> {
+        match kind {
+            TransactionKind::Transfer => {
+                vec![system_instruction::transfer(from, to, self.transfer_amount)]
+            }
+            TransactionKind::NukeSpam => vec![nuke_program::spam(
+                &self.nuke_program_id,
+                from,
+                to,
+                from,
+                SpamArgs {
+                    random: rng.gen(),
+                    loop_counter: rng.gen_range(
+                        *self.loop_counter.start() as u32,
+                        *self.loop_counter.end() as u32 + 1,
+                    ) as u16,
+                    amount: self.transfer_amount,
+                },
+            )],
+            // The random payload keeps memos from the same payer from sharing a signature
+            TransactionKind::Memo => vec![Instruction::new_with_bytes(
+                memo::id(),
+                format!("nuke {}", rng.gen::<u64>()).as_bytes(),
+                vec![AccountMeta::new_readonly(*from, true)],
+            )],
+        }
+    }
+
+    /// Draw a kind from the mix and build a signed transaction of it, after the
+    /// `compute_budget` instructions. With a `nonce_account`, `blockhash` is the nonce stored in
+    /// it and the transaction advances it.
+    pub fn transaction<R: Rng>(
+        &self,
+        from: &Keypair,
+        to: &Pubkey,
+        blockhash: &Hash,
+        nonce_account: Option<&Pubkey>,
+        compute_budget: &[Instruction],
+        rng: &mut R,
+    ) -> Transaction {
+        let kind = self.sample(rng);
+        let mut instructions = compute_budget.to_vec();
+        instructions.extend(self.instructions(kind, &from.pubkey(), to, rng));
+        let message = match nonce_account {
+            Some(nonce_account) => nonced_message(&instructions, &from.pubkey(), nonce_account),
+            None => Message::new(&instructions, Some(&from.pubkey())),
+        };
+        Transaction::new(&[from], message, *blockhash)
+    }
+}
+
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let weights: Vec<String> = self
+            .weights
+            .iter()
+            .map(|(kind, weight)| format!("{}={}", kind.name(), weight))
+            .collect();
+        write!(f, "{}", weights.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        rand::{rngs::StdRng, SeedableRng},
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn test_parse_weights() {
+        assert_eq!(
+            Workload::parse_weights("transfer=70, spam = 20,memo=10"),
+            Ok(vec![
+                (TransactionKind::Transfer, 70),
+                (TransactionKind::NukeSpam, 20),
+                (TransactionKind::Memo, 10),
+            ])
+        );
+        for invalid in [
+            "transfer",
+            "transfer=",
+            "transfer=-1",
+            "transfer=1.5",
+            "vote=1",
+            "",
+        ] {
+            assert!(Workload::parse_weights(invalid).is_err(), "{}", invalid);
+        }
+
+        // Parsing alone doesn't catch a mix that can't draw anything
+        let zero = Workload::parse_weights("transfer=0,memo=0").unwrap();
+        assert!(Workload::new(zero).is_err());
+        assert!(Workload::new(vec![]).is_err());
+
+        let workload = Workload::new(Workload::parse_weights("spam=3,memo=1").unwrap()).unwrap();
+        assert_eq!(workload.to_string(), "spam=3,memo=1");
+    }
+
+    #[test]
+    fn test_weighted_sampling() {
+        let weights = Workload::parse_weights("transfer=70,spam=20,memo=10,transfer=0").unwrap();
+        let workload = Workload::new(weights).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts: HashMap<TransactionKind, usize> = HashMap::new();
+        for _ in 0..10_000 {
+            *counts.entry(workload.sample(&mut rng)).or_default() += 1;
+        }
+        for (kind, expected) in [
+            (TransactionKind::Transfer, 7_000),
+            (TransactionKind::NukeSpam, 2_000),
+            (TransactionKind::Memo, 1_000),
+        ] {
+            let count = counts[&kind];
+            assert!(
+                (count as i64 - expected).abs() < 300,
+                "{:?} drawn {} times",
+                kind,
+                count
+            );
+        }
+
+        // A kind weighted 0 is never drawn
+        let weights = Workload::parse_weights("transfer=1,memo=0").unwrap();
+        let transfers_only = Workload::new(weights).unwrap();
+        assert!((0..1_000).all(|_| transfers_only.sample(&mut rng) == TransactionKind::Transfer));
+    }
+}